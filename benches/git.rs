@@ -0,0 +1,110 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[macro_use]
+extern crate criterion;
+extern crate git2;
+extern crate megaprompt;
+extern crate prompt_buffer;
+extern crate tempdir;
+
+use criterion::Criterion;
+use git2::{IndexAddOption, Repository, Signature};
+use megaprompt::git::GitPlugin;
+use prompt_buffer::{PluginSpeed, PromptBufferPlugin, ShellType};
+use std::cmp;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use tempdir::TempDir;
+
+const FILES_PER_DIR: usize = 100;
+const MODIFIED: usize = 10;
+
+/// Builds a repository with `files` committed files, the first few of which
+/// are modified in the working directory.
+fn generate_repo(dir: &Path, files: usize) {
+    let repo = Repository::init(dir).expect("Unable to init repo");
+
+    for i in 0..files {
+        let parent = dir.join(format!("dir_{}", i / FILES_PER_DIR));
+        fs::create_dir_all(&parent).expect("Unable to create dir");
+        let mut f = File::create(parent.join(format!("file_{}.txt", i)))
+            .expect("Unable to create file");
+        writeln!(f, "file {}", i).expect("Unable to write file");
+    }
+
+    let mut index = repo.index().expect("Unable to open index");
+    index
+        .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+        .expect("Unable to add files");
+    index.write().expect("Unable to write index");
+    let tree = repo.find_tree(index.write_tree().expect("Unable to write tree"))
+        .expect("Unable to find tree");
+    let sig = Signature::now("Bench", "bench@example.com").expect("Unable to create signature");
+    repo.commit(Some("HEAD"), &sig, &sig, "Initial commit", &tree, &[])
+        .expect("Unable to commit");
+
+    for i in 0..cmp::min(MODIFIED, files) {
+        let mut f = File::create(
+            dir.join(format!("dir_{}", i / FILES_PER_DIR))
+                .join(format!("file_{}.txt", i)),
+        ).expect("Unable to open file");
+        writeln!(f, "modified {}", i).expect("Unable to write file");
+    }
+}
+
+fn git_plugin(c: &mut Criterion) {
+    let repos = [10, 10_000, 100_000]
+        .iter()
+        .map(|&files| {
+            let dir = TempDir::new("megaprompt-bench").expect("Unable to create temp dir");
+            generate_repo(dir.path(), files);
+            (files, dir)
+        })
+        .collect::<Vec<_>>();
+    let paths = repos
+        .iter()
+        .map(|&(files, ref dir)| (files, dir.path().to_path_buf()))
+        .collect::<Vec<_>>();
+
+    for speed in &[PluginSpeed::Fast, PluginSpeed::Slow] {
+        let speed = *speed;
+        let paths = paths.clone();
+        c.bench_function_over_inputs(
+            &format!(
+                "GitPlugin {}",
+                match speed {
+                    PluginSpeed::Fast => "fast",
+                    _ => "slow",
+                }
+            ),
+            move |b, &&files| {
+                let path = &paths
+                    .iter()
+                    .find(|&&(f, _)| f == files)
+                    .expect("Repo was not generated")
+                    .1;
+                let mut plugin = GitPlugin::new();
+                b.iter(|| {
+                    let mut lines = Vec::new();
+                    plugin.run(speed, ShellType::Zsh, path, &mut lines);
+                    lines
+                })
+            },
+            &[10, 10_000, 100_000],
+        );
+    }
+}
+
+criterion_group!{
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = git_plugin
+}
+criterion_main!(benches);
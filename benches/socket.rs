@@ -0,0 +1,116 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Measures a full request to a running daemon, the same way the client
+//! makes one.
+
+#[macro_use]
+extern crate criterion;
+extern crate git2;
+extern crate tempdir;
+extern crate unix_socket;
+
+use criterion::Criterion;
+use std::env;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+use tempdir::TempDir;
+use unix_socket::UnixStream;
+
+struct Daemon {
+    child: Child,
+    socket: PathBuf,
+    _dir: TempDir,
+}
+
+impl Daemon {
+    fn start() -> Daemon {
+        let dir = TempDir::new("megaprompt-daemon").expect("Unable to create temp dir");
+        let socket = dir.path().join("socket");
+
+        // Benchmarks live in target/<profile>/deps, next to the binary's directory
+        let mut exe = env::current_exe().expect("No current exe");
+        exe.pop();
+        exe.pop();
+        exe.push("megaprompt");
+
+        let child = Command::new(exe)
+            .arg("--daemon")
+            .env("MEGAPROMPT_DAEMON_SOCKET", &socket)
+            .env("MEGAPROMPT_DAEMON_LOG", dir.path().join("log"))
+            .env("XDG_CACHE_HOME", dir.path().join("cache"))
+            .spawn()
+            .expect("Unable to start daemon");
+
+        for _ in 0..500 {
+            if socket.exists() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Daemon {
+            child: child,
+            socket: socket,
+            _dir: dir,
+        }
+    }
+
+    fn request(&self, path: &Path) -> String {
+        let mut stream = UnixStream::connect(&self.socket).expect("Unable to connect");
+        write!(stream, "!2 {} Zsh", path.display()).expect("Unable to write request");
+        stream
+            .shutdown(Shutdown::Write)
+            .expect("Cannot shutdown stream");
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .expect("Unable to read response");
+        response
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn round_trip(c: &mut Criterion) {
+    let plain = TempDir::new("megaprompt-plain").expect("Unable to create temp dir");
+    let repo = TempDir::new("megaprompt-repo").expect("Unable to create temp dir");
+    git2::Repository::init(repo.path()).expect("Unable to init repo");
+
+    let daemon = Daemon::start();
+    let paths = vec![
+        ("plain", plain.path().to_path_buf()),
+        ("repo", repo.path().to_path_buf()),
+    ];
+
+    c.bench_function_over_inputs(
+        "daemon round trip",
+        move |b, &&name| {
+            let path = &paths
+                .iter()
+                .find(|&&(n, _)| n == name)
+                .expect("Unknown path")
+                .1;
+            b.iter(|| daemon.request(path))
+        },
+        &["plain", "repo"],
+    );
+}
+
+criterion_group!(benches, round_trip);
+criterion_main!(benches);
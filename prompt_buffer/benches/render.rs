@@ -0,0 +1,60 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[macro_use]
+extern crate criterion;
+extern crate prompt_buffer;
+
+use criterion::Criterion;
+use prompt_buffer::{PluginSpeed, PromptBuffer, PromptBufferPlugin, PromptLines, ShellType};
+use std::path::PathBuf;
+
+/// Produces the same shape of output as the git plugin: a boxed header with
+/// indented free lines under it, followed by a branch line.
+struct CannedPlugin {
+    entries: usize,
+}
+
+impl PromptBufferPlugin for CannedPlugin {
+    fn run(&mut self, _: PluginSpeed, shell: ShellType, _: &PathBuf, lines: &mut PromptLines) {
+        if self.entries > 0 {
+            lines.push(shell.new_line().block("Git Status").build());
+            for i in 0..self.entries {
+                lines.push(
+                    shell
+                        .new_free_line()
+                        .indent()
+                        .block(format!(" M src/file_{}.rs", i))
+                        .build(),
+                );
+            }
+        }
+        lines.push(
+            shell
+                .new_line()
+                .block("master")
+                .indent_by(if self.entries > 0 { 1 } else { 0 })
+                .build(),
+        );
+    }
+}
+
+fn render(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "convert_to_string_ext",
+        |b, &&entries| {
+            let mut buffer = PromptBuffer::new(ShellType::Zsh);
+            buffer.add_plugin(CannedPlugin { entries: entries });
+            b.iter(|| buffer.convert_to_string_ext(PluginSpeed::Slow))
+        },
+        &[0, 10, 100],
+    );
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -128,13 +128,32 @@ impl PromptBuffer {
             }
         }
 
+        // The line connecting the prompt enters each row at the column it left
+        // the previous one, and leaves at the level of the next row. Free rows
+        // keep a straight line at their own level when a boxed row follows, so
+        // the boxed row takes the step instead.
+        let bottoms = lines
+            .iter()
+            .enumerate()
+            .map(|(ix, line)| match lines.get(ix + 1) {
+                None => 0,
+                Some(next) => match (&line.line_type, &next.line_type) {
+                    (&PromptLineType::Free, &PromptLineType::Boxed) => line.level,
+                    _ => next.level,
+                },
+            })
+            .collect::<Vec<_>>();
+
         for (ix, line) in lines.iter().enumerate() {
-            let current = line.level;
-            let (after, start, end) = if ix + 1 < lines.len() {
-                let a = lines[ix + 1].level;
-                (a, cmp::min(current, a), cmp::max(current, a))
-            } else {
-                (0, 0, current)
+            let top = if ix > 0 { Some(bottoms[ix - 1]) } else { None };
+            let bottom = bottoms[ix];
+            let start = cmp::min(top.unwrap_or(bottom), bottom);
+            let end = cmp::max(top.unwrap_or(bottom), bottom);
+            let end = match line.line_type {
+                // Boxed rows reach right to their own level, except the first,
+                // which starts where the line goes down
+                PromptLineType::Boxed if top.is_some() => cmp::max(end, line.level),
+                _ => end,
             };
 
             let mut line_text = String::new();
@@ -148,17 +167,14 @@ impl PromptBuffer {
                     "{}{}",
                     line_text,
                     PromptBuffer::get_line(
-                        if i == current && ix > 0 { TOP } else { 0 } | if i == after {
-                            BOTTOM
-                        } else {
-                            0
-                        } | if i > start { LEFT } else { 0 }
+                        if top == Some(i) { TOP } else { 0 } | if i == bottom { BOTTOM } else { 0 }
+                            | if i > start { LEFT } else { 0 }
                             | match line.line_type {
                                 PromptLineType::Boxed => RIGHT,
-                                PromptLineType::Free => if i == current {
-                                    0
-                                } else {
+                                PromptLineType::Free => if i < end {
                                     RIGHT
+                                } else {
+                                    0
                                 },
                             }
                     )
@@ -0,0 +1,83 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Describes spans of time in words, for plugins showing ages and deadlines
+
+use calendar::{civil_from_days, days_in_month, div_floor, DAY};
+
+/// Describes the time between two instants, given in seconds since the Unix
+/// epoch, using at most `precision` adjacent units starting from the largest.
+///
+/// Months and years follow the calendar (in UTC) rather than being a fixed
+/// number of days, so January 15th to March 15th is exactly `2 months`. The
+/// order of the instants doesn't matter.
+///
+/// ```
+/// use prompt_buffer::humanize_duration;
+///
+/// assert_eq!(humanize_duration(0, 90_061, 2), "1 day 1 hour");
+/// assert_eq!(humanize_duration(0, 90_061, 4), "1 day 1 hour 1 minute 1 second");
+/// assert_eq!(humanize_duration(0, 0, 2), "0 seconds");
+/// ```
+pub fn humanize_duration(from: i64, to: i64, precision: usize) -> String {
+    let (from, to) = if from <= to { (from, to) } else { (to, from) };
+
+    let (from_year, from_month, from_day) = civil_from_days(div_floor(from, DAY));
+    let (to_year, to_month, to_day) = civil_from_days(div_floor(to, DAY));
+
+    let mut years = to_year - from_year;
+    let mut months = to_month - from_month;
+    let mut days = to_day - from_day;
+    let mut seconds = (to - div_floor(to, DAY) * DAY) - (from - div_floor(from, DAY) * DAY);
+
+    if seconds < 0 {
+        seconds += DAY;
+        days -= 1;
+    }
+
+    // Borrow whole months, counting back from the month before `to`
+    let (mut year, mut month) = (to_year, to_month);
+    while days < 0 {
+        month -= 1;
+        if month == 0 {
+            month = 12;
+            year -= 1;
+        }
+        days += days_in_month(year, month);
+        months -= 1;
+    }
+    if months < 0 {
+        months += 12;
+        years -= 1;
+    }
+
+    let units = [
+        (years, "year"),
+        (months, "month"),
+        (days, "day"),
+        (seconds / 3600, "hour"),
+        (seconds % 3600 / 60, "minute"),
+        (seconds % 60, "second"),
+    ];
+
+    let parts = units
+        .iter()
+        .skip_while(|&&(amount, _)| amount == 0)
+        .take(precision)
+        .filter(|&&(amount, _)| amount > 0)
+        .map(|&(amount, unit)| {
+            format!("{} {}{}", amount, unit, if amount == 1 { "" } else { "s" })
+        })
+        .collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        "0 seconds".to_owned()
+    } else {
+        parts.join(" ")
+    }
+}
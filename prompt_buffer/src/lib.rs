@@ -21,12 +21,16 @@ extern crate num;
 extern crate term;
 
 mod buffer;
+mod calendar;
+mod humanize;
 mod thread;
 mod line;
 mod error;
 mod shell;
 
 pub use buffer::{PluginSpeed, PromptBuffer, PromptBufferPlugin};
+pub use calendar::{civil_from_days, days_from_civil, days_in_month, div_floor, DAY};
+pub use humanize::humanize_duration;
 pub use shell::ShellType;
 pub use thread::PromptThread;
 pub use line::PromptLines;
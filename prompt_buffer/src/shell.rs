@@ -73,6 +73,11 @@ impl ShellType {
         self.col_cmd(&format!("1;{}m", c + 30))
     }
 
+    /// Returns a blink escape sequence
+    pub fn blink(&self) -> String {
+        self.col_cmd(&"5m".to_owned())
+    }
+
     /// Returns a reset sequence
     pub fn reset(&self) -> String {
         self.col_cmd(&"0m".to_owned())
@@ -14,12 +14,16 @@
 //! leaking too many threads.
 use std::time::Duration;
 use std::thread;
+use std::sync::{Arc, Mutex};
 use chan::{self, Receiver, Sender};
 use std::path::PathBuf;
 
 use buffer::{PluginSpeed, PromptBuffer};
 use error::PromptBufferResult;
 
+/// How many callers may wait on one slow prompt at a time
+const MAX_WAITERS: usize = 4;
+
 /// Stores information about prompt threads
 pub struct PromptThread {
     send: Sender<()>,
@@ -28,6 +32,9 @@ pub struct PromptThread {
     path: PathBuf,
     cached: String,
     alive: bool,
+    timeout: Duration,
+    pending: bool,
+    waiters: Arc<Mutex<Vec<Sender<String>>>>,
 }
 
 fn oneshot_timer(dur: Duration) -> Receiver<()> {
@@ -51,8 +58,10 @@ impl PromptThread {
         let (tx_notify, rx_notify) = chan::async();
         let (tx_prompt, rx_prompt) = chan::async();
         let (tx_death, rx_death) = chan::async();
+        let waiters: Arc<Mutex<Vec<Sender<String>>>> = Arc::new(Mutex::new(Vec::new()));
 
         let p = path.clone();
+        let thread_waiters = waiters.clone();
         let mut prompt = make_prompt();
         let cached = prompt.convert_to_string_ext(PluginSpeed::Fast);
         let name = format!("{}", path.display());
@@ -68,10 +77,22 @@ impl PromptThread {
                     {
                         chan_select! {
                             rx_notify.recv() => {
-                                tx_prompt.send(prompt.convert_to_string())
+                                let text = prompt.convert_to_string();
+                                match thread_waiters.lock() {
+                                    Ok(mut waiting) => {
+                                        tx_prompt.send(text.clone());
+                                        for waiter in waiting.drain(..) {
+                                            waiter.send(text.clone());
+                                        }
+                                    }
+                                    Err(_) => tx_prompt.send(text),
+                                }
                             },
                             timeout.recv() => {
                                 info!("Thread {} timed out", name);
+                                if let Ok(mut waiting) = thread_waiters.lock() {
+                                    waiting.clear();
+                                }
                                 let _ = tx_death.send(());
                                 break;
                             }
@@ -88,6 +109,9 @@ impl PromptThread {
             path: path,
             cached: cached,
             alive: true,
+            timeout: Duration::from_millis(50),
+            pending: false,
+            waiters: waiters,
         })
     }
 
@@ -107,13 +131,52 @@ impl PromptThread {
         self.alive
     }
 
+    /// Sets how long `get` waits for a fresh prompt before returning the
+    /// cached one. Defaults to 50 milliseconds.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Replaces the cached prompt, e.g. with one saved by a previous run
+    pub fn set_cached(&mut self, cached: String) {
+        self.cached = cached;
+    }
+
+    /// Whether the last call to `get` returned the cached prompt because the
+    /// fresh one took too long
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
     fn revive(&mut self, make_prompt: &Fn() -> PromptBuffer) -> PromptBufferResult<()> {
+        let timeout = self.timeout;
+        let cached = self.cached.clone();
         *self = try!(PromptThread::new(self.path.clone(), make_prompt));
+        self.timeout = timeout;
+        self.cached = cached;
         Ok(())
     }
 
+    /// Takes prompts rendered for earlier requests, which are still buffered
+    /// after `get` timed out, so they aren't mistaken for a fresh answer
+    fn drain(&mut self) {
+        loop {
+            let ref recv = self.recv;
+            #[allow(unused_mut)]
+            {
+                chan_select! {
+                    default => return,
+                    recv.recv() -> text => match text {
+                        Some(t) => self.cached = t,
+                        None => return,
+                    },
+                }
+            }
+        }
+    }
+
     /// Gets a result out of the prompt thread, or return a cached result
-    /// if the response takes more than 100 milliseconds
+    /// if the response takes longer than the timeout
     pub fn get(&mut self, make_prompt: &Fn() -> PromptBuffer) -> PromptBufferResult<String> {
         info!("Checking lifesigns");
         if !self.check_is_alive() {
@@ -121,11 +184,13 @@ impl PromptThread {
             self.revive(make_prompt)?;
         }
 
+        self.drain();
+
         info!("Asking for a new prompt");
         self.send.send(());
 
         info!("Creating timeout");
-        let timeout = oneshot_timer(Duration::from_millis(50));
+        let timeout = oneshot_timer(self.timeout);
 
         loop {
             let ref recv = self.recv;
@@ -137,11 +202,13 @@ impl PromptThread {
                         info!("Got text");
                         if let Some(t) = text {
                             self.cached = t;
+                            self.pending = false;
                             return Ok(self.cached.clone());
                         }
                     },
                     timeout.recv() => {
                         info!("Got timeout");
+                        self.pending = true;
                         return Ok(self.cached.clone());
                     }
                 }
@@ -149,4 +216,44 @@ impl PromptThread {
             thread::sleep(Duration::from_millis(1));
         }
     }
+
+    /// Waits for the prompt that was still being rendered when the last call
+    /// to `get` gave up and returned the cached result.
+    ///
+    /// Returns `None` if the last `get` was answered with a fresh prompt, as
+    /// there is nothing newer to wait for, or if `MAX_WAITERS` callers are
+    /// already waiting.
+    pub fn subscribe(&mut self) -> Option<Receiver<String>> {
+        if !self.pending || !self.check_is_alive() {
+            return None;
+        }
+
+        let mut waiting = match self.waiters.lock() {
+            Ok(w) => w,
+            Err(_) => return None,
+        };
+        if waiting.len() >= MAX_WAITERS {
+            return None;
+        }
+
+        let (tx, rx) = chan::async();
+        let ref recv = self.recv;
+        #[allow(unused_mut)]
+        {
+            chan_select! {
+                default => {
+                    waiting.push(tx);
+                },
+                recv.recv() -> text => {
+                    if let Some(t) = text {
+                        self.cached = t.clone();
+                        self.pending = false;
+                        tx.send(t);
+                    }
+                },
+            }
+        }
+
+        Some(rx)
+    }
 }
@@ -0,0 +1,181 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Renders hand built `PromptLines` and compares them to the files in
+//! `tests/golden`. Run with `UPDATE_GOLDEN=1` to rewrite the golden files
+//! after an intentional change to the output.
+
+extern crate prompt_buffer;
+
+use prompt_buffer::{PluginSpeed, PromptBuffer, PromptBufferPlugin, PromptLines, ShellType};
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+struct Canned(PromptLines);
+
+impl PromptBufferPlugin for Canned {
+    fn run(&mut self, _: PluginSpeed, _: ShellType, _: &PathBuf, lines: &mut PromptLines) {
+        lines.extend(self.0.iter().cloned());
+    }
+}
+
+/// Replaces the bash color escapes with `<..>` so the golden files stay readable
+fn visualize(prompt: &str) -> String {
+    prompt.replace("\\[\x1B[", "<").replace("m\\]", ">")
+}
+
+fn render(plugins: Vec<PromptLines>) -> String {
+    let mut buffer = PromptBuffer::new(ShellType::Bash);
+    for lines in plugins {
+        buffer.add_plugin(Canned(lines));
+    }
+    visualize(&buffer.convert_to_string_ext(PluginSpeed::Slow))
+}
+
+fn check(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.txt", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        let mut f = File::create(&path).expect("Unable to create golden file");
+        f.write_all(actual.as_bytes())
+            .expect("Unable to write golden file");
+        return;
+    }
+
+    let mut expected = String::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_string(&mut expected))
+        .expect("Missing golden file, run with UPDATE_GOLDEN=1 to create it");
+
+    assert!(
+        expected == actual,
+        "{} does not match {}\n--- expected\n{}\n--- actual\n{}",
+        name,
+        path.display(),
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn no_plugins() {
+    check("no_plugins", &render(vec![]));
+}
+
+#[test]
+fn empty_plugin_output() {
+    check("empty_plugin_output", &render(vec![vec![], vec![]]));
+}
+
+#[test]
+fn single_boxed_line() {
+    let shell = ShellType::Bash;
+    check(
+        "single_boxed_line",
+        &render(vec![
+            vec![shell.new_line().block("one").colored_block("two", 6).build()],
+        ]),
+    );
+}
+
+#[test]
+fn section_with_free_lines() {
+    let shell = ShellType::Bash;
+    check(
+        "section_with_free_lines",
+        &render(vec![
+            vec![
+                shell.new_line().colored_block("Git Status", 6).build(),
+                shell.new_free_line().indent().colored_block(" M a.rs", 4).build(),
+                shell.new_free_line().indent().bold_colored_block("A  b.rs", 2).build(),
+                shell.new_line().colored_block("master", 6).indent().build(),
+            ],
+        ]),
+    );
+}
+
+#[test]
+fn nested_indents() {
+    let shell = ShellType::Bash;
+    check(
+        "nested_indents",
+        &render(vec![
+            vec![
+                shell.new_line().block("zero").build(),
+                shell.new_line().block("one").indent().build(),
+                shell.new_line().block("two").indent_by(2).build(),
+                shell.new_free_line().block("three").indent_by(3).build(),
+                shell.new_line().block("two again").indent_by(2).build(),
+                shell.new_line().block("one again").indent().build(),
+            ],
+        ]),
+    );
+}
+
+#[test]
+fn level_drop_of_more_than_one() {
+    let shell = ShellType::Bash;
+    check(
+        "level_drop_of_more_than_one",
+        &render(vec![
+            vec![
+                shell.new_line().block("deep").indent_by(3).build(),
+                shell.new_line().block("top").build(),
+            ],
+        ]),
+    );
+}
+
+#[test]
+fn level_rise_of_more_than_one() {
+    let shell = ShellType::Bash;
+    check(
+        "level_rise_of_more_than_one",
+        &render(vec![
+            vec![
+                shell.new_line().block("top").build(),
+                shell.new_line().block("deep").indent_by(3).build(),
+            ],
+        ]),
+    );
+}
+
+#[test]
+fn free_line_before_deeper_line() {
+    let shell = ShellType::Bash;
+    check(
+        "free_line_before_deeper_line",
+        &render(vec![
+            vec![
+                shell.new_free_line().block("free").build(),
+                shell.new_line().block("boxed").indent_by(2).build(),
+            ],
+        ]),
+    );
+}
+
+#[test]
+fn multiple_plugins() {
+    let shell = ShellType::Bash;
+    check(
+        "multiple_plugins",
+        &render(vec![
+            vec![shell.new_line().block("Project is due in: 2 days").build()],
+            vec![
+                shell.new_line().colored_block("Git Outgoing", 6).build(),
+                shell.new_free_line().indent().block("abc1234 Commit").build(),
+                shell.new_line().colored_block("master", 6).indent().build(),
+            ],
+        ]),
+    );
+}
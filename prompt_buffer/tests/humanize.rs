@@ -0,0 +1,75 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate prompt_buffer;
+
+use prompt_buffer::{days_from_civil, humanize_duration, DAY};
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+
+/// Seconds since the epoch of midnight UTC on a date
+fn date(year: i64, month: i64, day: i64) -> i64 {
+    days_from_civil(year, month, day) * DAY
+}
+
+#[test]
+fn singular_and_plural() {
+    assert_eq!(humanize_duration(0, 1, 2), "1 second");
+    assert_eq!(humanize_duration(0, 2, 2), "2 seconds");
+    assert_eq!(humanize_duration(0, MINUTE, 2), "1 minute");
+    assert_eq!(humanize_duration(0, 2 * HOUR, 2), "2 hours");
+    assert_eq!(humanize_duration(0, DAY, 2), "1 day");
+    assert_eq!(humanize_duration(0, 0, 2), "0 seconds");
+}
+
+#[test]
+fn precision() {
+    let span = 3 * DAY + 4 * HOUR + 5 * MINUTE + 6;
+    assert_eq!(humanize_duration(0, span, 1), "3 days");
+    assert_eq!(humanize_duration(0, span, 2), "3 days 4 hours");
+    assert_eq!(humanize_duration(0, span, 4), "3 days 4 hours 5 minutes 6 seconds");
+    assert_eq!(humanize_duration(0, span, 0), "0 seconds");
+}
+
+#[test]
+fn skips_zero_units_without_widening() {
+    // 2 days and 30 seconds is "2 days" at a precision of 2, not "2 days 30 seconds"
+    assert_eq!(humanize_duration(0, 2 * DAY + 30, 2), "2 days");
+    assert_eq!(humanize_duration(0, 2 * DAY + 30, 4), "2 days 30 seconds");
+}
+
+#[test]
+fn order_does_not_matter() {
+    assert_eq!(humanize_duration(5 * HOUR, 0, 2), "5 hours");
+    assert_eq!(humanize_duration(-DAY, 0, 2), "1 day");
+}
+
+#[test]
+fn calendar_months() {
+    assert_eq!(humanize_duration(date(2017, 1, 15), date(2017, 3, 15), 2), "2 months");
+    assert_eq!(humanize_duration(date(2017, 2, 1), date(2017, 3, 1), 2), "1 month");
+    assert_eq!(humanize_duration(date(2017, 1, 31), date(2017, 3, 1), 2), "29 days");
+    assert_eq!(humanize_duration(date(2016, 1, 31), date(2016, 3, 1), 2), "30 days");
+    assert_eq!(humanize_duration(date(2016, 1, 29), date(2016, 3, 1), 2), "1 month 1 day");
+    assert_eq!(
+        humanize_duration(date(2017, 11, 20), date(2017, 12, 19) + 23 * HOUR, 2),
+        "29 days 23 hours"
+    );
+}
+
+#[test]
+fn calendar_years() {
+    assert_eq!(humanize_duration(date(2016, 2, 29), date(2017, 2, 28), 2), "11 months 30 days");
+    assert_eq!(humanize_duration(date(2016, 3, 1), date(2017, 3, 1), 2), "1 year");
+    assert_eq!(humanize_duration(date(2000, 6, 1), date(2017, 8, 2), 2), "17 years 2 months");
+    assert_eq!(
+        humanize_duration(date(1969, 12, 31), date(1970, 1, 1) + HOUR, 3),
+        "1 day 1 hour"
+    );
+}
@@ -0,0 +1,73 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Drives a `PromptThread` whose slow render takes longer than its timeout.
+
+extern crate prompt_buffer;
+
+use prompt_buffer::{PluginSpeed, PromptBuffer, PromptBufferPlugin, PromptLines, PromptThread,
+                    ShellType};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Counts its slow renders, each taking 200 milliseconds
+struct Slow(Arc<AtomicUsize>);
+
+impl PromptBufferPlugin for Slow {
+    fn run(&mut self, speed: PluginSpeed, shell: ShellType, _: &PathBuf, lines: &mut PromptLines) {
+        let text = match speed {
+            PluginSpeed::Slow => {
+                thread::sleep(Duration::from_millis(200));
+                format!("render {}", self.0.fetch_add(1, Ordering::SeqCst) + 1)
+            }
+            _ => "fast".to_owned(),
+        };
+        lines.push(shell.new_line().block(text).build());
+    }
+}
+
+fn slow_thread() -> (PromptThread, Box<Fn() -> PromptBuffer>) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let make_prompt: Box<Fn() -> PromptBuffer> = Box::new(move || {
+        let mut buffer = PromptBuffer::new(ShellType::Bash);
+        buffer.add_plugin(Slow(count.clone()));
+        buffer
+    });
+    let mut thread = PromptThread::new(PathBuf::from("/"), &*make_prompt).ok().expect("Unable to start thread");
+    thread.set_timeout(Duration::from_millis(10));
+    (thread, make_prompt)
+}
+
+#[test]
+fn waited_prompt_is_not_returned_again() {
+    let (mut thread, make_prompt) = slow_thread();
+
+    let first = thread.get(&*make_prompt).ok().expect("Unable to get prompt");
+    assert!(thread.is_pending());
+    assert!(first.contains("fast"), "{}", first);
+
+    let waiter = thread.subscribe().expect("The render is still pending");
+    let waited = waiter.recv().expect("The waiter got no prompt");
+    assert!(waited.contains("render 1"), "{}", waited);
+
+    thread.set_timeout(Duration::from_secs(5));
+    let second = thread.get(&*make_prompt).ok().expect("Unable to get prompt");
+    assert!(second.contains("render 2"), "{}", second);
+}
+
+#[test]
+fn waiters_are_capped() {
+    let (mut thread, make_prompt) = slow_thread();
+    thread.get(&*make_prompt).ok().expect("Unable to get prompt");
+
+    let waiters = (0..10).filter_map(|_| thread.subscribe()).collect::<Vec<_>>();
+    assert_eq!(waiters.len(), 4);
+}
@@ -0,0 +1,81 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Keeps the last prompt rendered for each directory on disk.
+//!
+//! The daemon writes to it whenever it renders a fresh prompt and reads from
+//! it when it starts a thread for a directory, so a restart doesn't go back to
+//! prompts without git status. The client reads it directly when the daemon is
+//! slow or down.
+
+use prompt_buffer::ShellType;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use settings;
+
+pub struct PromptCache {
+    dir: PathBuf,
+}
+
+// FNV-1a, which is stable across builds unlike the std hashers
+fn hash_path(path: &Path) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for b in format!("{}", path.display()).bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+impl PromptCache {
+    pub fn new() -> Option<PromptCache> {
+        settings::xdg_dir("XDG_CACHE_HOME", ".cache").map(|dir| PromptCache {
+            dir: dir.join("megaprompt"),
+        })
+    }
+
+    fn file(&self, path: &Path, shell: ShellType) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}-{:?}", hash_path(path), shell))
+    }
+
+    /// Returns the last prompt stored for a path, if any
+    pub fn load(&self, path: &Path, shell: ShellType) -> Option<String> {
+        let mut contents = String::new();
+        match File::open(self.file(path, shell)) {
+            Ok(mut f) => if f.read_to_string(&mut contents).is_err() {
+                return None;
+            },
+            Err(_) => return None,
+        }
+
+        // The first line records the path, in case two paths share a hash
+        let mut parts = contents.splitn(2, '\n');
+        match (parts.next(), parts.next()) {
+            (Some(stored), Some(prompt)) if stored == format!("{}", path.display()) => {
+                Some(prompt.to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    /// Stores the prompt for a path, replacing whatever was there
+    pub fn store(&self, path: &Path, shell: ShellType, prompt: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let target = self.file(path, shell);
+        let temp = target.with_extension("tmp");
+        {
+            let mut f = File::create(&temp)?;
+            write!(f, "{}\n{}", path.display(), prompt)?;
+        }
+        fs::rename(temp, target)
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reads `.due` files.
+//!
+//! A `.due` file holds any number of named deadlines, each in its own section:
+//!
+//! ```text
+//! [release]
+//! due = 2017-11-03T17:00:00+02:00
+//! title = Release 1.0
+//!
+//! [demo]
+//! due = next friday 15:00
+//! future = starts in
+//! past = started
+//! ```
+//!
+//! `title` defaults to the section name, `future` to `is due in` and `past`
+//! to `was due`. `bands` overrides the urgency bands, as described in
+//! `urgency`, and `repeat` makes the deadline recur, as described in `repeat`.
+//! Keys before the first section describe an unnamed deadline, except `bands`
+//! which applies to every deadline in the file that doesn't set its own.
+//!
+//! The original format, a date matching `when::LEGACY_FORMAT` followed by a
+//! title, a future and a past wording line, is still read.
+
+use time;
+
+use super::when;
+
+pub const DEFAULT_TITLE: &'static str = "Project";
+pub const DEFAULT_FUTURE: &'static str = "is due in";
+pub const DEFAULT_PAST: &'static str = "was due";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deadline {
+    /// The section the deadline came from, `None` for the unnamed one
+    pub name: Option<String>,
+    /// The date expression, resolved with `when::parse`
+    pub due: String,
+    pub title: String,
+    pub future: String,
+    pub past: String,
+    /// Urgency bands for this deadline, overriding the `due.bands` setting
+    pub bands: Option<String>,
+    /// A recurrence rule, read with `repeat::Repeat`
+    pub repeat: Option<String>,
+}
+
+/// The contents of a `.due` file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DueFile {
+    /// Urgency bands for every deadline without its own, from before the first
+    /// section
+    pub bands: Option<String>,
+    /// The deadlines, the unnamed one first. Ones without a date are kept so
+    /// rewriting the file doesn't lose them.
+    pub deadlines: Vec<Deadline>,
+    /// Lines that were ignored, and why
+    pub problems: Vec<String>,
+}
+
+impl Deadline {
+    /// A deadline with the default wording and no date yet
+    pub fn new(name: Option<String>) -> Deadline {
+        Deadline {
+            title: name.clone().unwrap_or_else(|| DEFAULT_TITLE.to_owned()),
+            name: name,
+            due: String::new(),
+            future: DEFAULT_FUTURE.to_owned(),
+            past: DEFAULT_PAST.to_owned(),
+            bands: None,
+            repeat: None,
+        }
+    }
+
+    /// Whether the deadline has a date or recurrence rule to resolve
+    pub fn is_dated(&self) -> bool {
+        !self.due.is_empty() || self.repeat.is_some()
+    }
+}
+
+fn parse_legacy(contents: &str) -> DueFile {
+    let mut lines = contents.lines().map(|l| l.trim());
+    let mut deadline = Deadline::new(None);
+    deadline.due = lines.next().unwrap_or("").to_owned();
+
+    for field in &mut [
+        &mut deadline.title,
+        &mut deadline.future,
+        &mut deadline.past,
+    ] {
+        match lines.next() {
+            Some(line) if !line.is_empty() => **field = line.to_owned(),
+            _ => {}
+        }
+    }
+
+    DueFile {
+        bands: None,
+        deadlines: vec![deadline],
+        problems: Vec::new(),
+    }
+}
+
+/// Reads a `.due` file in either format
+pub fn parse(contents: &str) -> DueFile {
+    let first = contents.lines().next().unwrap_or("").trim();
+    if time::strptime(first, when::LEGACY_FORMAT).is_ok() {
+        return parse_legacy(contents);
+    }
+
+    let mut bands = None;
+    let mut deadlines = vec![Deadline::new(None)];
+    let mut problems = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_owned();
+            deadlines.push(Deadline::new(Some(name)));
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let deadline = deadlines
+            .last_mut()
+            .expect("There is always a deadline");
+        match (parts.next().map(|k| k.trim()), parts.next().map(|v| v.trim())) {
+            (Some("due"), Some(value)) => deadline.due = value.to_owned(),
+            (Some("title"), Some(value)) => deadline.title = value.to_owned(),
+            (Some("future"), Some(value)) => deadline.future = value.to_owned(),
+            (Some("past"), Some(value)) => deadline.past = value.to_owned(),
+            (Some("bands"), Some(value)) if deadline.name.is_none() => {
+                bands = Some(value.to_owned())
+            }
+            (Some("bands"), Some(value)) => deadline.bands = Some(value.to_owned()),
+            (Some("repeat"), Some(value)) => deadline.repeat = Some(value.to_owned()),
+            (Some(key), Some(_)) => {
+                problems.push(format!("line {}: unknown key {:?}", number + 1, key))
+            }
+            _ => problems.push(format!(
+                "line {}: expected \"key = value\" or \"[section]\", not {:?}",
+                number + 1,
+                line
+            )),
+        }
+    }
+
+    // An unnamed deadline with nothing but defaults was only a placeholder for
+    // the keys before the first section
+    if deadlines[0] == Deadline::new(None) {
+        let _ = deadlines.remove(0);
+    }
+
+    DueFile {
+        bands: bands,
+        deadlines: deadlines,
+        problems: problems,
+    }
+}
+
+/// Writes a `.due` file back out in the sectioned format, leaving out keys
+/// that have their default value. An unnamed deadline must come first.
+pub fn render(file: &DueFile) -> String {
+    let mut out = String::new();
+    if let Some(ref bands) = file.bands {
+        out.push_str(&format!("bands = {}\n", bands));
+    }
+    for deadline in &file.deadlines {
+        let default_title = match deadline.name {
+            Some(ref name) => {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&format!("[{}]\n", name));
+                name.as_str()
+            }
+            None => DEFAULT_TITLE,
+        };
+
+        if !deadline.due.is_empty() {
+            out.push_str(&format!("due = {}\n", deadline.due));
+        }
+        if let Some(ref repeat) = deadline.repeat {
+            out.push_str(&format!("repeat = {}\n", repeat));
+        }
+        if deadline.title != default_title {
+            out.push_str(&format!("title = {}\n", deadline.title));
+        }
+        if deadline.future != DEFAULT_FUTURE {
+            out.push_str(&format!("future = {}\n", deadline.future));
+        }
+        if deadline.past != DEFAULT_PAST {
+            out.push_str(&format!("past = {}\n", deadline.past));
+        }
+        if let Some(ref bands) = deadline.bands {
+            out.push_str(&format!("bands = {}\n", bands));
+        }
+    }
+    out
+}
@@ -0,0 +1,191 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Edits `.due` files for the `megaprompt due` subcommand.
+//!
+//! Dates are checked with `when::parse` before anything is written, and are
+//! stored as absolute ISO-8601 times so that `tomorrow` doesn't move. Relative
+//! dates already in the file count from its mtime, so they are turned into
+//! absolute ones the same way before it changes. Files are rewritten with
+//! `file::render`, which drops comments and converts the original format to
+//! the sectioned one.
+
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use time::{self, Timespec, Tm};
+
+use super::{written, PathTraversal};
+use super::file::{self, Deadline, DueFile};
+use super::repeat::Repeat;
+use super::when;
+
+/// Formats a point in time as ISO-8601 in the local UTC offset in force then
+pub fn format_iso(at: Timespec) -> String {
+    let offset = time::at(at).tm_utcoff as i64;
+    let local = time::at_utc(Timespec::new(at.sec + offset, 0));
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!(
+        "{}{}{:02}:{:02}",
+        local
+            .strftime("%Y-%m-%dT%H:%M:%S")
+            .expect("The format is valid"),
+        sign,
+        offset.abs() / 3600,
+        offset.abs() % 3600 / 60
+    )
+}
+
+/// Reads a `.due` file, an empty one if it doesn't exist
+pub fn read(path: &Path) -> Result<DueFile, String> {
+    let mut contents = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+        Ok(_) => Ok(file::parse(&contents)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(DueFile::default()),
+        Err(e) => Err(format!("unable to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Reads a `.due` file that is about to be rewritten, with its relative dates
+/// made absolute while its mtime still says when they were written
+fn read_for_update(path: &Path, now: &Tm) -> Result<DueFile, String> {
+    let written = written(path, now);
+    let mut due_file = read(path)?;
+    for deadline in &mut due_file.deadlines {
+        if deadline.due.is_empty() || when::parse_absolute(&deadline.due).is_some() {
+            continue;
+        }
+        if let Some(at) = when::parse(&deadline.due, &written) {
+            deadline.due = format_iso(at);
+        }
+    }
+    Ok(due_file)
+}
+
+fn write(path: &Path, due_file: &DueFile) -> Result<(), String> {
+    if due_file.deadlines.is_empty() {
+        return match fs::remove_file(path) {
+            Err(ref e) if e.kind() != ErrorKind::NotFound => {
+                Err(format!("unable to remove {}: {}", path.display(), e))
+            }
+            _ => Ok(()),
+        };
+    }
+
+    // Write next to the file and rename over it, so a failed write or a
+    // prompt reading it halfway never sees a truncated file
+    let temp = path.with_file_name(format!(".due.{}.tmp", process::id()));
+    let written = File::create(&temp)
+        .and_then(|mut f| {
+            f.write_all(file::render(due_file).as_bytes())?;
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(format!("unable to write {}: {}", path.display(), e));
+    }
+    Ok(())
+}
+
+/// Adds or replaces the deadline called `name` in the `.due` file in `dir`,
+/// returning when it is next due. Like the title, a recurrence rule is only
+/// changed when `repeat` is given, `Some(None)` removing it.
+pub fn set(
+    dir: &Path,
+    name: Option<&str>,
+    due: &str,
+    title: Option<&str>,
+    repeat: Option<Option<&str>>,
+    now: &Tm,
+) -> Result<Timespec, String> {
+    let at = match when::parse(due, now) {
+        Some(at) => at,
+        None => return Err(format!("unable to understand the date {:?}", due)),
+    };
+    if let Some(name) = name {
+        if name.is_empty() || name.contains(|c| c == '[' || c == ']' || c == '\n') {
+            return Err(format!("invalid deadline name {:?}", name));
+        }
+    }
+    if title.map_or(false, |t| t.contains('\n')) {
+        return Err("titles must fit on one line".to_owned());
+    }
+
+    let path = dir.join(".due");
+    let mut due_file = read_for_update(&path, now)?;
+    let name = name.map(|n| n.to_owned());
+
+    let index = match due_file.deadlines.iter().position(|d| d.name == name) {
+        Some(index) => index,
+        None if name.is_none() => {
+            due_file.deadlines.insert(0, Deadline::new(None));
+            0
+        }
+        None => {
+            due_file.deadlines.push(Deadline::new(name));
+            due_file.deadlines.len() - 1
+        }
+    };
+
+    let next = {
+        let deadline = &mut due_file.deadlines[index];
+        if let Some(repeat) = repeat {
+            deadline.repeat = repeat.map(|r| r.to_owned());
+        }
+        let next = match deadline.repeat {
+            Some(ref repeat) => repeat.parse::<Repeat>()?.next(Some(at), now)?,
+            None => at,
+        };
+
+        deadline.due = format_iso(at);
+        if let Some(title) = title {
+            deadline.title = title.to_owned();
+        }
+        next
+    };
+
+    write(&path, &due_file)?;
+    Ok(next)
+}
+
+/// Removes the deadline called `name` from the `.due` file in `dir`, or the
+/// whole file without a name, returning how many deadlines were removed
+pub fn clear(dir: &Path, name: Option<&str>, now: &Tm) -> Result<usize, String> {
+    let path = dir.join(".due");
+    let mut due_file = read_for_update(&path, now)?;
+    let before = due_file.deadlines.len();
+
+    match name {
+        Some(name) => due_file
+            .deadlines
+            .retain(|d| d.name.as_ref().map(|n| n.as_str()) != Some(name)),
+        None => due_file.deadlines.clear(),
+    }
+
+    let removed = before - due_file.deadlines.len();
+    if removed == 0 && name.is_some() {
+        return Ok(0);
+    }
+    write(&path, &due_file)?;
+    Ok(removed)
+}
+
+/// Every `.due` file from `path` up to the root, nearest first, with its
+/// contents
+pub fn list(path: &Path) -> Vec<(PathBuf, Result<DueFile, String>)> {
+    PathTraversal::new(&path.to_path_buf())
+        .map(|dir| dir.join(".due"))
+        .filter(|file| file.is_file())
+        .map(|file| {
+            let deadlines = read(&file);
+            (file, deadlines)
+        })
+        .collect()
+}
@@ -0,0 +1,252 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use prompt_buffer::{humanize_duration, PluginSpeed, PromptBufferPlugin, PromptLines, ShellType};
+use term::color;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
+use std::io::Read;
+use std::time::UNIX_EPOCH;
+use std::usize;
+use time::{self, Timespec, Tm};
+
+use self::file::{Deadline, DueFile};
+use self::repeat::Repeat;
+use self::urgency::Bands;
+use settings::Settings;
+
+pub mod file;
+pub mod manage;
+pub mod repeat;
+pub mod urgency;
+pub mod when;
+
+pub struct DueDatePlugin {
+    bands: Bands,
+    precision: usize,
+    nearest: usize,
+}
+
+impl Default for DueDatePlugin {
+    fn default() -> DueDatePlugin {
+        let settings = Settings::load();
+        DueDatePlugin {
+            bands: settings.get_or("due.bands", Bands::default()),
+            precision: settings.get_or("due.precision", 2),
+            nearest: settings.get_or("due.nearest", 0),
+        }
+    }
+}
+
+impl DueDatePlugin {
+    pub fn new() -> DueDatePlugin {
+        DueDatePlugin::default()
+    }
+
+    /// Overrides the `due.nearest` setting, 0 reading every `.due` file
+    pub fn set_nearest(&mut self, nearest: usize) {
+        self.nearest = nearest;
+    }
+}
+
+struct PathTraversal {
+    path: PathBuf,
+}
+
+impl PathTraversal {
+    fn new(p: &PathBuf) -> PathTraversal {
+        let mut pat = p.clone();
+        pat.push("dummy");
+        PathTraversal { path: pat }
+    }
+}
+
+impl Iterator for PathTraversal {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        if !self.path.pop() {
+            return None;
+        };
+        Some(self.path.clone())
+    }
+}
+
+/// When the `.due` file at `path` was last modified, which relative dates in
+/// it count from. `now` if that isn't known.
+pub fn written(path: &Path, now: &Tm) -> Tm {
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok());
+    match modified {
+        Some(since) => time::at(Timespec::new(since.as_secs() as i64, 0)),
+        None => *now,
+    }
+}
+
+/// Works out when a deadline from a file last modified at `written` is due,
+/// the next occurrence for recurring ones
+pub fn resolve(deadline: &Deadline, written: &Tm, now: &Tm) -> Result<Timespec, String> {
+    let due = if deadline.due.is_empty() {
+        None
+    } else {
+        match when::parse(&deadline.due, written) {
+            Some(due) => Some(due),
+            None => return Err(format!("unable to parse due date {:?}", deadline.due)),
+        }
+    };
+
+    match deadline.repeat {
+        Some(ref repeat) => repeat.parse::<Repeat>()?.next(due, now),
+        None => due.ok_or_else(|| "no due date".to_owned()),
+    }
+}
+
+/// Shortens `dir` for display, writing the home directory as `~`
+fn origin_label(dir: &Path) -> String {
+    match env::home_dir().and_then(|home| dir.strip_prefix(&home).ok().map(|p| p.to_path_buf())) {
+        Some(ref rest) if rest.as_os_str().is_empty() => "~".to_owned(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => dir.display().to_string(),
+    }
+}
+
+/// A deadline resolved to a point in time, with the directory it came from
+struct Entry {
+    origin: String,
+    deadline: Deadline,
+    /// The deadline's own urgency bands, or else the file's
+    bands: Option<String>,
+    due: Timespec,
+}
+
+impl PromptBufferPlugin for DueDatePlugin {
+    fn run(&mut self, _: PluginSpeed, shell: ShellType, path: &PathBuf, lines: &mut PromptLines) {
+        let now = time::now();
+        let now_sec = now.to_timespec().sec;
+
+        let mut entries = Vec::new();
+        let mut warnings = Vec::new();
+
+        let nearest = if self.nearest == 0 {
+            usize::MAX
+        } else {
+            self.nearest
+        };
+        let dirs = PathTraversal::new(path)
+            .filter(|dir| dir.join(".due").is_file())
+            .take(nearest);
+
+        for dir in dirs {
+            let origin = origin_label(&dir);
+            let path = dir.join(".due");
+
+            let mut contents = String::new();
+            if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)) {
+                warn!("Unable to read {}: {}", path.display(), e);
+                warnings.push(format!("{}: unable to read .due: {}", origin, e));
+                continue;
+            }
+
+            let written = written(&path, &now);
+            let DueFile {
+                bands,
+                deadlines,
+                problems,
+            } = file::parse(&contents);
+            for problem in problems {
+                warn!("Ignoring {} of {}", problem, path.display());
+                warnings.push(format!("{}: .due {}", origin, problem));
+            }
+            for deadline in deadlines {
+                if !deadline.is_dated() {
+                    warn!("Deadline {:?} has no due date", deadline.title);
+                    warnings.push(format!("{}: {}: no due date", origin, deadline.title));
+                    continue;
+                }
+                match resolve(&deadline, &written, &now) {
+                    Ok(due) => entries.push(Entry {
+                        origin: origin.clone(),
+                        bands: deadline.bands.clone().or_else(|| bands.clone()),
+                        deadline: deadline,
+                        due: due,
+                    }),
+                    Err(e) => {
+                        warn!("Unable to resolve {:?}: {}", deadline.title, e);
+                        warnings.push(format!("{}: {}: {}", origin, deadline.title, e));
+                    }
+                }
+            }
+        }
+
+        // Most urgent first; the sort is stable, so ties keep the nearest first
+        entries.sort_by_key(|entry| entry.due.sec);
+
+        for entry in entries {
+            let deadline = entry.deadline;
+            let bands = match entry.bands.as_ref().map(|b| b.parse::<Bands>()) {
+                Some(Ok(bands)) => bands,
+                Some(Err(e)) => {
+                    warn!("Ignoring bands for {:?}: {}", deadline.title, e);
+                    warnings.push(format!(
+                        "{}: invalid bands for {}: {}",
+                        entry.origin, deadline.title, e
+                    ));
+                    self.bands.clone()
+                }
+                None => self.bands.clone(),
+            };
+
+            let band = bands.for_remaining(entry.due.sec - now_sec);
+
+            let (temporal, postfix) = if entry.due.sec < now_sec {
+                (deadline.past, " ago")
+            } else {
+                (deadline.future, "")
+            };
+
+            let due_phrase = format!(
+                "{}{}{} {}: {}{}{}{}",
+                shell.col(color::MAGENTA),
+                band.icon
+                    .as_ref()
+                    .map(|icon| format!("{} ", icon))
+                    .unwrap_or_default(),
+                deadline.title,
+                temporal,
+                if band.bold {
+                    shell.bcol(band.color)
+                } else {
+                    shell.col(band.color)
+                },
+                if band.blink { shell.blink() } else { String::new() },
+                humanize_duration(now_sec, entry.due.sec, self.precision),
+                postfix
+            );
+
+            lines.push(
+                shell
+                    .new_line()
+                    .colored_block(entry.origin, color::WHITE)
+                    .block(due_phrase)
+                    .build(),
+            );
+        }
+
+        for warning in warnings {
+            lines.push(
+                shell
+                    .new_line()
+                    .colored_block(warning, color::YELLOW)
+                    .build(),
+            );
+        }
+    }
+}
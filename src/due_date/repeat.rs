@@ -0,0 +1,247 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Recurrence rules for deadlines that come back, such as weekly demos.
+//!
+//! * `daily`, `weekly`, `biweekly` or `every N days` / `every N weeks` repeat
+//!   a fixed time after `due`
+//! * `monthly` repeats on the day of the month of `due`, `monthly on 15` on
+//!   the 15th, using the last day of shorter months
+//! * `cron M H DOM MON DOW` takes a crontab schedule, with `*`, lists, ranges
+//!   and steps. `due` is optional, and no occurrence before it is used
+//!
+//! Occurrences are worked out in local wall-clock time, so a weekly deadline
+//! at 15:00 stays at 15:00 across daylight saving changes. The UTC offset of
+//! each one comes from the system time zone at that moment.
+
+use prompt_buffer::{civil_from_days, days_from_civil, days_in_month, div_floor, DAY};
+use std::str::FromStr;
+use time::{Timespec, Tm};
+
+use super::when::{checked_time, from_local, to_local};
+
+/// How long to look ahead for a crontab match; leap days need 8 years
+const CRON_SEARCH_DAYS: i64 = 8 * 366;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Repeat {
+    /// A fixed number of seconds after `due`
+    Every(i64),
+    /// A day of every month, `None` for the day of `due`
+    Monthly(Option<i64>),
+    Cron(Cron),
+}
+
+/// A crontab schedule, each field a bit set of the values it matches
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_number(s: &str, min: u32, max: u32) -> Result<u32, String> {
+    match s.parse() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        _ => Err(format!("{:?} is not between {} and {}", s, min, max)),
+    }
+}
+
+/// Parses a crontab field such as `*`, `1-5`, `*/15` or `0,30` into a bit set
+fn parse_field(s: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in s.split(',') {
+        let mut pieces = part.splitn(2, '/');
+        let range = pieces.next().unwrap_or("");
+        let step = match pieces.next() {
+            Some(step) => parse_number(step, 1, max)?,
+            None => 1,
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(dash) = range.find('-') {
+            (
+                parse_number(&range[..dash], min, max)?,
+                parse_number(&range[dash + 1..], min, max)?,
+            )
+        } else {
+            let start = parse_number(range, min, max)?;
+            (start, if step > 1 { max } else { start })
+        };
+        if start > end {
+            return Err(format!("{:?} is an empty range", part));
+        }
+
+        let mut value = start;
+        while value <= end {
+            bits |= 1 << value;
+            value += step;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Cron, String> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(format!("{:?} needs 5 fields", s));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Cron {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays: weekdays,
+            // Like Vixie cron, `*/2` is as unrestricted as `*`
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+}
+
+impl Cron {
+    fn matches_day(&self, day: i64) -> bool {
+        let (_, month, dom) = civil_from_days(day);
+        // 1970-01-01 was a Thursday
+        let weekday = (day % 7 + 11) % 7;
+        let dom = self.days & (1 << dom) != 0;
+        let weekday = self.weekdays & (1 << weekday) != 0;
+
+        // As in cron, a restricted day of month and weekday match either
+        self.months & (1 << month) != 0 && match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => dom,
+            (false, false) => dom || weekday,
+        }
+    }
+
+    /// The first match at or after `start`, both in local seconds
+    fn next(&self, start: i64) -> Option<i64> {
+        let first_day = div_floor(start, DAY);
+        (first_day..first_day + CRON_SEARCH_DAYS)
+            .filter(|&day| self.matches_day(day))
+            .filter_map(|day| {
+                (0..24)
+                    .filter(|hour| self.hours & (1 << hour) != 0)
+                    .flat_map(|hour| {
+                        (0..60)
+                            .filter(move |minute| self.minutes & (1 << minute) != 0)
+                            .map(move |minute| day * DAY + hour * 3600 + minute * 60)
+                    })
+                    .find(|&at| at >= start)
+            })
+            .next()
+    }
+}
+
+impl FromStr for Repeat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Repeat, String> {
+        let lower = s.trim().to_lowercase();
+        let words = lower.split_whitespace().collect::<Vec<_>>();
+
+        Ok(match &words[..] {
+            &["daily"] => Repeat::Every(DAY),
+            &["weekly"] => Repeat::Every(7 * DAY),
+            &["biweekly"] => Repeat::Every(14 * DAY),
+            &["every", amount, unit] => {
+                let amount: i64 = match amount.parse() {
+                    Ok(amount) if amount > 0 => amount,
+                    _ => return Err(format!("invalid repeat {:?}", s)),
+                };
+                let period = match unit.trim_right_matches('s') {
+                    "day" => amount.checked_mul(DAY),
+                    "week" => amount.checked_mul(7 * DAY),
+                    _ => return Err(format!("invalid repeat {:?}", s)),
+                };
+                match period {
+                    Some(period) => Repeat::Every(period),
+                    None => return Err(format!("{:?} repeats too rarely", s)),
+                }
+            }
+            &["monthly"] => Repeat::Monthly(None),
+            &["monthly", "on", day] => Repeat::Monthly(Some(parse_number(day, 1, 31)? as i64)),
+            _ if words.first() == Some(&"cron") => {
+                let fields = lower.trim_left_matches("cron");
+                Repeat::Cron(fields.parse()?)
+            }
+            _ => return Err(format!("invalid repeat {:?}", s)),
+        })
+    }
+}
+
+impl Repeat {
+    /// The first occurrence at or after `now`, and not before `due`
+    pub fn next(&self, due: Option<Timespec>, now: &Tm) -> Result<Timespec, String> {
+        let now = to_local(now.to_timespec().sec);
+
+        let start = match (self, due) {
+            (&Repeat::Cron(ref cron), due) => {
+                let start = due.map_or(now, |due| ::std::cmp::max(now, to_local(due.sec)));
+                // Crontab times are whole minutes
+                let start = div_floor(start + 59, 60) * 60;
+                return cron.next(start)
+                    .map(from_local)
+                    .ok_or_else(|| "the crontab schedule never matches".to_owned());
+            }
+            (_, Some(due)) => to_local(due.sec),
+            (_, None) => return Err("repeating needs a due date to start from".to_owned()),
+        };
+
+        let next = match *self {
+            Repeat::Every(period) => if start >= now {
+                start
+            } else {
+                let periods = (now - start - 1) / period + 1;
+                let next = periods.checked_mul(period).and_then(|p| p.checked_add(start));
+                match next.and_then(|next| checked_time(next).map(|_| next)) {
+                    Some(next) => next,
+                    None => return Err("the next occurrence is too far away".to_owned()),
+                }
+            },
+            Repeat::Monthly(day) => {
+                let clock = start - div_floor(start, DAY) * DAY;
+                let (year, month, due_day) = civil_from_days(div_floor(start, DAY));
+                let day = day.unwrap_or(due_day);
+
+                // Skip ahead to the month before now, then step to the first match
+                let (now_year, now_month, _) = civil_from_days(div_floor(now, DAY));
+                let mut index = ::std::cmp::max(year * 12 + month - 1, now_year * 12 + now_month - 2);
+                loop {
+                    let (year, month) = (div_floor(index, 12), index - div_floor(index, 12) * 12 + 1);
+                    let day = ::std::cmp::min(day, days_in_month(year, month));
+                    let at = days_from_civil(year, month, day) * DAY + clock;
+                    if at >= start && at >= now {
+                        break at;
+                    }
+                    index += 1;
+                }
+            }
+            Repeat::Cron(_) => unreachable!("Handled above"),
+        };
+
+        Ok(from_local(next))
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Picks how a deadline is drawn from how much time is left.
+//!
+//! Bands are written as a comma separated list, each band being a threshold, a
+//! color, optional `bold` and `blink` flags and an optional icon:
+//!
+//! ```text
+//! past red bold, 1h red blink 🔥, 48h yellow, 7d cyan, * green
+//! ```
+//!
+//! A band applies when less than its threshold is left. Thresholds are a
+//! number followed by `s`, `m`, `h`, `d` or `w`. `past` applies once the
+//! deadline has passed and `*` applies when no threshold does.
+
+use std::str::FromStr;
+use term::color;
+
+pub const DEFAULT_BANDS: &'static str = "past red bold, 1h red blink, 48h yellow, 7d cyan, * green";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Band {
+    pub color: u32,
+    pub bold: bool,
+    pub blink: bool,
+    pub icon: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bands {
+    past: Band,
+    /// Bands with a threshold in seconds, smallest first
+    upcoming: Vec<(i64, Band)>,
+    later: Band,
+}
+
+impl Default for Bands {
+    fn default() -> Bands {
+        DEFAULT_BANDS
+            .parse()
+            .expect("The default bands are valid")
+    }
+}
+
+impl Bands {
+    /// The band for a deadline `seconds` away, negative once it has passed
+    pub fn for_remaining(&self, seconds: i64) -> &Band {
+        if seconds < 0 {
+            return &self.past;
+        }
+        self.upcoming
+            .iter()
+            .find(|&&(threshold, _)| seconds < threshold)
+            .map(|&(_, ref band)| band)
+            .unwrap_or(&self.later)
+    }
+}
+
+fn parse_color(name: &str) -> Option<u32> {
+    Some(match name {
+        "black" => color::BLACK,
+        "red" => color::RED,
+        "green" => color::GREEN,
+        "yellow" => color::YELLOW,
+        "blue" => color::BLUE,
+        "magenta" => color::MAGENTA,
+        "cyan" => color::CYAN,
+        "white" => color::WHITE,
+        _ => return None,
+    })
+}
+
+/// Parses a threshold such as `90s`, `30m`, `48h`, `7d` or `2w` into seconds
+fn parse_threshold(s: &str) -> Option<i64> {
+    let (amount, unit) = match s.char_indices().last() {
+        Some((last, _)) => s.split_at(last),
+        None => return None,
+    };
+    let amount: i64 = amount.parse().ok()?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    amount.checked_mul(unit)
+}
+
+impl FromStr for Bands {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Bands, String> {
+        let mut past = None;
+        let mut upcoming = Vec::new();
+        let mut later = None;
+
+        for spec in s.split(',') {
+            let mut words = spec.split_whitespace();
+            let threshold = match words.next() {
+                Some(threshold) => threshold,
+                None => continue,
+            };
+            let color = match words.next() {
+                Some(name) => match parse_color(name) {
+                    Some(color) => color,
+                    None => return Err(format!("unknown color {:?}", name)),
+                },
+                None => return Err(format!("band {:?} has no color", spec.trim())),
+            };
+
+            let mut band = Band {
+                color: color,
+                bold: false,
+                blink: false,
+                icon: None,
+            };
+            for word in words {
+                match word {
+                    "bold" => band.bold = true,
+                    "blink" => band.blink = true,
+                    icon if band.icon.is_none() => band.icon = Some(icon.to_owned()),
+                    _ => return Err(format!("band {:?} has more than one icon", spec.trim())),
+                }
+            }
+
+            match threshold {
+                "past" => past = Some(band),
+                "*" => later = Some(band),
+                _ => match parse_threshold(threshold) {
+                    Some(seconds) => upcoming.push((seconds, band)),
+                    None => return Err(format!("invalid threshold {:?}", threshold)),
+                },
+            }
+        }
+
+        upcoming.sort_by_key(|&(threshold, _)| threshold);
+        let later = match later.or_else(|| upcoming.last().map(|&(_, ref band)| band.clone())) {
+            Some(later) => later,
+            None => return Err("no bands".to_owned()),
+        };
+
+        Ok(Bands {
+            past: past.unwrap_or_else(|| Band {
+                color: color::RED,
+                bold: false,
+                blink: false,
+                icon: None,
+            }),
+            upcoming: upcoming,
+            later: later,
+        })
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parses the dates in `.due` files. Three forms are understood:
+//!
+//! * The original `Fri Nov 03 17:00:00 2017`, read as UTC
+//! * ISO-8601 dates such as `2017-11-03`, `2017-11-03T17:00` or
+//!   `2017-11-03T17:00:00+02:00`, in local time unless they have an offset
+//! * Relative expressions such as `tomorrow`, `friday 17:00`,
+//!   `next monday 09:30` or `in 3 days`, resolved against `now`. For dates
+//!   read from a `.due` file that is when the file was last modified.
+//!
+//! Local times use the UTC offset the system time zone has at that time, so a
+//! date on the other side of a daylight saving change keeps its clock time.
+
+use prompt_buffer::{days_from_civil, days_in_month, div_floor, DAY};
+use time::{self, Timespec, Tm};
+
+/// The format of the first line of the original `.due` files
+pub const LEGACY_FORMAT: &'static str = "%a %b %d %H:%M:%S %Y";
+
+const WEEKDAYS: [&'static str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// Resolves a date expression to a point in time
+pub fn parse(s: &str, now: &Tm) -> Option<Timespec> {
+    parse_absolute(s).or_else(|| parse_relative(s.trim(), now))
+}
+
+/// Like `parse`, but only for dates that don't depend on `now`, leaving out
+/// the relative expressions
+pub fn parse_absolute(s: &str) -> Option<Timespec> {
+    let s = s.trim();
+    if let Ok(tm) = time::strptime(s, LEGACY_FORMAT) {
+        return Some(tm.to_timespec());
+    }
+    parse_iso(s)
+}
+
+/// The start of the year 10000. Later dates can't be written as ISO-8601 and
+/// are past what the C library converts to local time on some systems.
+fn end_of_time() -> i64 {
+    days_from_civil(10_000, 1, 1) * DAY
+}
+
+/// `at` as a point in time, unless it is too far from 1970 to work with
+pub fn checked_time(at: i64) -> Option<Timespec> {
+    if at.abs() < end_of_time() {
+        Some(Timespec::new(at, 0))
+    } else {
+        None
+    }
+}
+
+/// Local wall-clock seconds since the epoch of a point in time
+pub fn to_local(at: i64) -> i64 {
+    at + time::at(Timespec::new(at, 0)).tm_utcoff as i64
+}
+
+/// The point in time of local wall-clock seconds since the epoch. Times that
+/// a daylight saving change skips come out an hour off.
+pub fn from_local(local: i64) -> Timespec {
+    let guess = local - time::at(Timespec::new(local, 0)).tm_utcoff as i64;
+    Timespec::new(local - time::at(Timespec::new(guess, 0)).tm_utcoff as i64, 0)
+}
+
+/// Parses `len` ASCII digits from the front of `s`
+fn digits(s: &str, len: usize) -> Option<(i64, &str)> {
+    if s.len() < len || !s.is_char_boundary(len)
+        || !s[..len].bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    s[..len].parse().ok().map(|n| (n, &s[len..]))
+}
+
+/// Parses `HH:MM` or `HH:MM:SS`, returning seconds since midnight
+fn parse_clock(s: &str) -> Option<(i64, &str)> {
+    let (hours, rest) = digits(s, 2)?;
+    if !rest.starts_with(':') {
+        return None;
+    }
+    let (minutes, rest) = digits(&rest[1..], 2)?;
+    let (seconds, rest) = if rest.starts_with(':') {
+        digits(&rest[1..], 2)?
+    } else {
+        (0, rest)
+    };
+
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some((hours * 3600 + minutes * 60 + seconds, rest))
+}
+
+/// Parses `Z`, `+HH:MM` or `+HHMM`, returning the offset in seconds
+fn parse_offset(s: &str) -> Option<i64> {
+    if s == "Z" {
+        return Some(0);
+    }
+    let sign = match s.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return None,
+    };
+    let (hours, rest) = digits(&s[1..], 2)?;
+    let rest = if rest.starts_with(':') { &rest[1..] } else { rest };
+    let (minutes, rest) = digits(rest, 2)?;
+    if !rest.is_empty() || hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn parse_iso(s: &str) -> Option<Timespec> {
+    let (year, rest) = digits(s, 4)?;
+    if !rest.starts_with('-') {
+        return None;
+    }
+    let (month, rest) = digits(&rest[1..], 2)?;
+    if !rest.starts_with('-') {
+        return None;
+    }
+    let (day, rest) = digits(&rest[1..], 2)?;
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let (clock, zone) = if rest.is_empty() {
+        (0, "")
+    } else if rest.starts_with('T') || rest.starts_with(' ') {
+        parse_clock(&rest[1..])?
+    } else {
+        return None;
+    };
+
+    let local = days_from_civil(year, month, day) * DAY + clock;
+    if zone.is_empty() {
+        return Some(from_local(local));
+    }
+    Some(Timespec::new(local - parse_offset(zone)?, 0))
+}
+
+fn parse_relative(s: &str, now: &Tm) -> Option<Timespec> {
+    let lower = s.to_lowercase();
+    let words = lower.split_whitespace().collect::<Vec<_>>();
+    let now_ts = now.to_timespec();
+
+    if words.len() == 3 && words[0] == "in" {
+        let amount: i64 = words[1].parse().ok()?;
+        let unit = match words[2].trim_right_matches('s') {
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => DAY,
+            "week" => 7 * DAY,
+            _ => return None,
+        };
+        return checked_time(amount.checked_mul(unit)?.checked_add(now_ts.sec)?);
+    }
+
+    let (next, words) = match words.split_first() {
+        Some((&"next", rest)) => (true, rest),
+        _ => (false, &words[..]),
+    };
+    let (day, clock) = match words.len() {
+        1 => (words[0], 0),
+        2 => match parse_clock(words[1]) {
+            Some((clock, "")) => (words[0], clock),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let local = to_local(now_ts.sec);
+    let today = div_floor(local, DAY);
+    let midnight = today * DAY;
+
+    let days = match day {
+        "today" if !next => 0,
+        "tomorrow" if !next => 1,
+        _ => {
+            let target = WEEKDAYS
+                .iter()
+                .position(|w| *w == day || (day.len() == 3 && w.starts_with(day)))?
+                as i64;
+            // 1970-01-01 was a Thursday
+            let weekday = (today % 7 + 11) % 7;
+            let days = (target - weekday + 7) % 7;
+            if days == 0 && (next || midnight + clock < local) {
+                7
+            } else {
+                days
+            }
+        }
+    };
+
+    Some(from_local(midnight + days * DAY + clock))
+}
@@ -0,0 +1,191 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fetches the remotes of repositories the daemon is serving prompts for, so
+//! the outgoing and upstream sections don't go stale.
+//!
+//! Fetching is opt in. Each repository is fetched at most once per interval,
+//! failures back off exponentially, and only a few fetches run at a time.
+//! Fetches are aborted once they run out of time. One stuck where libgit2
+//! can't abort it, such as while connecting, stops counting against the
+//! number running at a time instead.
+//! Nothing here ever asks for credentials: ssh keys are only taken from a
+//! running agent, and anything else that needs authentication fails.
+
+use git2::{Cred, CredentialType, Error, FetchOptions, RemoteCallbacks, Repository};
+use std::cmp;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use git::tracked_remote;
+
+/// How often the scheduler looks for repositories that are due, in milliseconds
+const TICK_MS: u64 = 500;
+
+/// Repositories without a prompt request for this many seconds are forgotten
+const IDLE_SECS: u64 = 60 * 60;
+
+/// The longest a failing repository is backed off, in multiples of the interval
+const MAX_BACKOFF: u32 = 16;
+
+/// Fetches the tracked remote of the repository containing `path`, giving up
+/// once transferring takes longer than `timeout`.
+///
+/// Returns `Ok(false)` if there is no remote to fetch.
+pub fn fetch_repo(path: &Path, timeout: Duration) -> Result<bool, Error> {
+    let deadline = Instant::now() + timeout;
+    let repo = try!(Repository::discover(path));
+    let name = match tracked_remote(&repo) {
+        Some(name) => name,
+        None => return Ok(false),
+    };
+    let mut remote = try!(repo.find_remote(&name));
+
+    let mut tried_agent = false;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|_, username, allowed| {
+        // libgit2 calls back again after a rejected credential, so only offer
+        // the agent once
+        if allowed.contains(CredentialType::SSH_KEY) && !tried_agent {
+            tried_agent = true;
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        Err(Error::from_str("megaprompt never prompts for credentials"))
+    });
+
+    // Returning false from a progress callback aborts the fetch
+    callbacks.transfer_progress(|_| Instant::now() < deadline);
+    callbacks.sideband_progress(|_| Instant::now() < deadline);
+
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+
+    let refspecs: &[&str] = &[];
+    try!(remote.fetch(refspecs, Some(&mut options), None));
+    Ok(true)
+}
+
+struct Entry {
+    due: Instant,
+    last_seen: Instant,
+    failures: u32,
+    /// When the fetch in flight started
+    running: Option<Instant>,
+}
+
+struct State {
+    repos: HashMap<PathBuf, Entry>,
+}
+
+/// Periodically fetches every repository passed to `watch`
+pub struct AutoFetch {
+    state: Arc<Mutex<State>>,
+}
+
+impl AutoFetch {
+    /// Starts the scheduler, fetching each repository every `interval` with at
+    /// most `jobs` fetches that haven't yet taken longer than `timeout` in
+    /// flight.
+    pub fn new(interval: Duration, jobs: usize, timeout: Duration) -> AutoFetch {
+        let state = Arc::new(Mutex::new(State {
+            repos: HashMap::new(),
+        }));
+
+        let scheduler = state.clone();
+        let _ = thread::spawn(move || loop {
+            schedule(&scheduler, interval, cmp::max(jobs, 1), timeout);
+            thread::sleep(Duration::from_millis(TICK_MS));
+        });
+
+        AutoFetch { state: state }
+    }
+
+    /// Marks the repository containing `path` as in use. Repositories are
+    /// fetched as soon as they're first seen.
+    pub fn watch(&self, path: &Path) {
+        let root = match Repository::discover(path) {
+            Ok(repo) => match repo.workdir() {
+                Some(workdir) => workdir.to_path_buf(),
+                None => repo.path().to_path_buf(),
+            },
+            Err(_) => return,
+        };
+
+        let now = Instant::now();
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        state
+            .repos
+            .entry(root)
+            .or_insert(Entry {
+                due: now,
+                last_seen: now,
+                failures: 0,
+                running: None,
+            })
+            .last_seen = now;
+    }
+}
+
+fn schedule(state: &Arc<Mutex<State>>, interval: Duration, jobs: usize, timeout: Duration) {
+    let now = Instant::now();
+    let mut guard = match state.lock() {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    // Fetches past their time are left to finish, but no longer hold up others
+    let in_time = |entry: &Entry| entry.running.map_or(false, |started| now < started + timeout);
+
+    guard
+        .repos
+        .retain(|_, entry| in_time(entry) || now.duration_since(entry.last_seen).as_secs() < IDLE_SECS);
+
+    let mut running = guard.repos.values().filter(|entry| in_time(entry)).count();
+    let due = guard
+        .repos
+        .iter()
+        .filter(|&(_, entry)| entry.running.is_none() && entry.due <= now)
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<_>>();
+
+    for path in due {
+        if running >= jobs {
+            break;
+        }
+        running += 1;
+        if let Some(entry) = guard.repos.get_mut(&path) {
+            entry.running = Some(now);
+        }
+
+        let state = state.clone();
+        let _ = thread::spawn(move || {
+            info!("Fetching {}", path.display());
+            let result = fetch_repo(&path, timeout);
+            if let Err(ref e) = result {
+                warn!("Unable to fetch {}: {}", path.display(), e.message());
+            }
+
+            let mut guard = match state.lock() {
+                Ok(guard) => guard,
+                Err(_) => return,
+            };
+            if let Some(entry) = guard.repos.get_mut(&path) {
+                entry.running = None;
+                entry.failures = if result.is_ok() { 0 } else { entry.failures + 1 };
+                let backoff = cmp::min(1 << cmp::min(entry.failures, 31), MAX_BACKOFF);
+                entry.due = Instant::now() + interval * backoff;
+            }
+        });
+    }
+}
@@ -9,10 +9,19 @@
 extern crate git2;
 extern crate term;
 
-use prompt_buffer::{PluginSpeed, PromptBufferPlugin, PromptLines, ShellType};
-use git2::{Error, Repository, StatusOptions};
-use std::{env, fmt};
+use prompt_buffer::{humanize_duration, PluginSpeed, PromptBufferPlugin, PromptLines, ShellType};
+use git2::{Branch, Commit, Config, DiffOptions, Error, ErrorCode, ObjectType, Oid, Patch, Repository,
+           Sort, StatusOptions, SubmoduleIgnore, SubmoduleStatus};
+use std::{cmp, env, fmt};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use term::color;
+
+use settings::Settings;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
@@ -42,6 +51,20 @@ impl RelativePath for PathBuf {
     }
 }
 
+/// Shows a path from the repository relative to the prompt's path
+fn display_path(workdir: &Path, file: &Path, cwd: &Path) -> PathBuf {
+    workdir
+        .join(file)
+        .make_relative(cwd)
+        .unwrap_or_else(|| PathBuf::from("/"))
+}
+
+fn short_id(id: Oid) -> String {
+    let mut id = format!("{}", id);
+    id.truncate(7);
+    id
+}
+
 enum StatusTypes {
     New,
     Modified,
@@ -120,73 +143,653 @@ fn get_git(path: &Path) -> Option<Repository> {
     Repository::discover(path).ok()
 }
 
+/// How the checked out branch relates to a remote branch
+enum Upstream {
+    /// Tracking the named remote branch
+    Tracking(String),
+    /// Configured to track the named branch, which no longer exists
+    Gone(String),
+    /// Not tracking anything, though the named remote exists
+    NotConfigured(String),
+    /// The repository has no remotes
+    LocalOnly,
+    /// HEAD is detached, so there is no branch to track anything
+    Detached,
+}
+
 struct BranchInfo {
     name: Option<String>,
-    upstream: Option<String>,
+    upstream: Upstream,
+    /// Tags pointing at HEAD
+    tags: Vec<String>,
+    /// Whether the branch has no commits yet
+    unborn: bool,
 }
 
-fn git_branch(repo: &Repository) -> Result<BranchInfo, Error> {
-    let branches = repo.branches(None).expect("Unable to load branches");
+fn head_tags(repo: &Repository, head: Oid) -> Vec<String> {
+    let mut tags = Vec::new();
 
-    for possible_branch in branches {
-        let branch = match possible_branch {
-            Ok((b, _)) => b,
-            Err(_) => continue,
-        };
+    if let Ok(references) = repo.references_glob("refs/tags/*") {
+        for reference in references {
+            let reference = match reference {
+                Ok(reference) => reference,
+                Err(_) => continue,
+            };
+
+            let points_at_head = reference
+                .peel_to_commit()
+                .map(|commit| commit.id() == head)
+                .unwrap_or(false);
+            if points_at_head {
+                if let Some(name) = reference.shorthand() {
+                    tags.push(name.to_owned());
+                }
+            }
+        }
+    }
 
-        if !branch.is_head() {
-            continue;
+    tags
+}
+
+/// Strips `refs/heads/` from a reference name
+fn short_ref(name: &str) -> String {
+    if name.starts_with("refs/heads/") {
+        name[11..].to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// The remote tracked by the current branch, falling back to `origin`
+pub fn tracked_remote(repo: &Repository) -> Option<String> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(_) => return None,
+    };
+
+    if head.is_branch() {
+        if let Some(name) = head.shorthand() {
+            let remote = repo.config()
+                .and_then(|c| c.get_string(&format!("branch.{}.remote", name)));
+            match remote {
+                Ok(ref remote) if remote == "." => return None,
+                Ok(remote) => return Some(remote),
+                Err(_) => {}
+            }
         }
+    }
 
-        let name = branch.name();
-        return Ok(BranchInfo {
-            name: match name {
-                Ok(n) => match n {
-                    Some(value) => Some(value.to_owned()),
-                    _ => None,
+    match repo.find_remote("origin") {
+        Ok(_) => Some("origin".to_owned()),
+        Err(_) => None,
+    }
+}
+
+/// The branch `HEAD` is compared against: `base` if set, otherwise the default
+/// branch of the tracked remote as recorded in `refs/remotes/<remote>/HEAD`
+fn base_branch(repo: &Repository, base: &str) -> Option<String> {
+    if !base.is_empty() {
+        return Some(base.to_owned());
+    }
+
+    let remote = match tracked_remote(repo) {
+        Some(remote) => remote,
+        None => return None,
+    };
+    let head = match repo.find_reference(&format!("refs/remotes/{}/HEAD", remote)) {
+        Ok(head) => head,
+        Err(_) => return None,
+    };
+    head.symbolic_target()
+        .map(|target| target.trim_left_matches("refs/remotes/").to_owned())
+}
+
+/// How many commits `HEAD` has that `base` doesn't, and the other way around
+fn divergence(repo: &Repository, base: &str) -> Result<(usize, usize), Error> {
+    let head = try!(try!(repo.revparse_single("HEAD")).peel(ObjectType::Commit)).id();
+    let base = try!(try!(repo.revparse_single(base)).peel(ObjectType::Commit)).id();
+    repo.graph_ahead_behind(head, base)
+}
+
+fn upstream_state(repo: &Repository, branch: Option<&Branch>, name: &str) -> Upstream {
+    if let Some(Ok(upstream)) = branch.map(|b| b.upstream()) {
+        if let Ok(Some(upstream)) = upstream.name() {
+            return Upstream::Tracking(upstream.to_owned());
+        }
+    }
+
+    let config = repo.config().ok();
+    let branch_config = |key: &str| {
+        config
+            .as_ref()
+            .and_then(|c| c.get_string(&format!("branch.{}.{}", name, key)).ok())
+    };
+
+    if let Some(merge) = branch_config("merge") {
+        let merge = short_ref(&merge);
+        return Upstream::Gone(match branch_config("remote") {
+            Some(ref remote) if remote != "." => format!("{}/{}", remote, merge),
+            _ => merge,
+        });
+    }
+
+    let remotes = match repo.remotes() {
+        Ok(remotes) => remotes
+            .iter()
+            .filter_map(|r| r.map(|r| r.to_owned()))
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+
+    if remotes.iter().any(|r| r == "origin") {
+        Upstream::NotConfigured("origin".to_owned())
+    } else {
+        match remotes.into_iter().next() {
+            Some(remote) => Upstream::NotConfigured(remote),
+            None => Upstream::LocalOnly,
+        }
+    }
+}
+
+/// Finds the branch and upstream of `HEAD`. Looking up the tags at `HEAD`
+/// costs a walk over every tag, so it is only done when `with_tags` is set.
+fn git_branch(repo: &Repository, with_tags: bool) -> Result<BranchInfo, Error> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(ref e) if e.code() == ErrorCode::UnbornBranch => {
+            let head = try!(repo.find_reference("HEAD"));
+            let name = head.symbolic_target().map(short_ref);
+            return Ok(BranchInfo {
+                upstream: match name {
+                    Some(ref name) => upstream_state(repo, None, name),
+                    None => Upstream::LocalOnly,
                 },
-                _ => None,
+                name: name,
+                tags: Vec::new(),
+                unborn: true,
+            });
+        }
+        Err(e) => return Err(e),
+    };
+
+    let target = match head.target() {
+        Some(target) => target,
+        None => return Err(Error::from_str("HEAD has no target")),
+    };
+    let tags = if with_tags {
+        head_tags(repo, target)
+    } else {
+        Vec::new()
+    };
+
+    if head.is_branch() {
+        let branch = Branch::wrap(head);
+        let name = try!(branch.name()).map(|n| n.to_owned());
+        return Ok(BranchInfo {
+            upstream: match name {
+                Some(ref name) => upstream_state(repo, Some(&branch), name),
+                None => Upstream::LocalOnly,
             },
-            upstream: match branch.upstream() {
-                Ok(upstream) => match upstream.name() {
-                    Ok(n) => match n {
-                        Some(value) => Some(value.to_owned()),
-                        _ => None,
-                    },
-                    _ => None,
-                },
-                Err(_) => None,
+            name: name,
+            tags: tags,
+            unborn: false,
+        });
+    }
+
+    let name = if tags.is_empty() {
+        let sid = try!(try!(repo.find_object(target, None)).short_id());
+        format!("detached {}", String::from_utf8_lossy(&sid))
+    } else {
+        format!("detached at {}", tags.join(", "))
+    };
+    Ok(BranchInfo {
+        name: Some(name),
+        upstream: Upstream::Detached,
+        tags: Vec::new(),
+        unborn: false,
+    })
+}
+
+/// Where the prompt's path is, relative to the repository
+enum Location {
+    WorkDir,
+    Bare,
+    GitDir,
+}
+
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    format!("{} {}", count, if count == 1 { singular } else { plural })
+}
+
+fn refs_summary(repo: &Repository) -> Result<String, Error> {
+    let mut branches = 0;
+    let mut tags = 0;
+
+    for reference in try!(repo.references()) {
+        let reference = match reference {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+
+        if reference.is_branch() {
+            branches += 1;
+        } else if reference.is_tag() {
+            tags += 1;
+        }
+    }
+
+    Ok(format!(
+        "{}, {}",
+        plural(branches, "branch", "branches"),
+        plural(tags, "tag", "tags")
+    ))
+}
+
+/// A checkout of the repository, either the main one or a linked worktree
+struct Worktree {
+    name: String,
+    path: PathBuf,
+    head: String,
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let mut contents = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+        Ok(_) => Some(contents.trim().to_owned()),
+        Err(_) => None,
+    }
+}
+
+/// Describes the contents of a HEAD file the way the branch line would
+fn describe_head(head: &str) -> String {
+    if head.starts_with("ref: ") {
+        short_ref(&head[5..])
+    } else {
+        let mut id = head.to_owned();
+        id.truncate(7);
+        id
+    }
+}
+
+/// The directory shared by every worktree of a repository
+fn common_dir(repo: &Repository) -> PathBuf {
+    if repo.is_worktree() {
+        if let Some(common) = read_trimmed(&repo.path().join("commondir")) {
+            let common = repo.path().join(common);
+            return fs::canonicalize(&common).unwrap_or(common);
+        }
+    }
+    repo.path().to_path_buf()
+}
+
+/// Lists every checkout of the repository, starting with the main one
+fn worktrees(repo: &Repository) -> Vec<Worktree> {
+    let common = common_dir(repo);
+    let mut result = Vec::new();
+
+    if common.file_name().map_or(false, |n| n == ".git") {
+        if let (Some(main), Some(head)) = (common.parent(), read_trimmed(&common.join("HEAD"))) {
+            result.push(Worktree {
+                name: "main".to_owned(),
+                path: main.to_path_buf(),
+                head: describe_head(&head),
+            });
+        }
+    }
+
+    let entries = match fs::read_dir(common.join("worktrees")) {
+        Ok(entries) => entries,
+        Err(_) => return result,
+    };
+
+    for entry in entries {
+        let dir = match entry {
+            Ok(entry) => entry.path(),
+            Err(_) => continue,
+        };
+        let gitdir = match read_trimmed(&dir.join("gitdir")) {
+            Some(gitdir) => PathBuf::from(gitdir),
+            None => continue,
+        };
+
+        result.push(Worktree {
+            name: match dir.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
             },
+            path: gitdir.parent().unwrap_or(&gitdir).to_path_buf(),
+            head: read_trimmed(&dir.join("HEAD"))
+                .map(|h| describe_head(&h))
+                .unwrap_or_else(|| "?".to_owned()),
         });
     }
 
-    match repo.head() {
-        Ok(r) => match repo.find_object(r.target().expect("Unable to find target"), None) {
-            Ok(obj) => {
-                let sid = obj.short_id().expect("Object has no short_id");
-                let s = sid.as_str();
-                let short_id = s.expect("Unable to convert short id to string");
-                Ok(BranchInfo {
-                    name: Some(short_id.to_owned()),
-                    upstream: Some("?".to_owned()),
-                })
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    result
+}
+
+/// How long ago `FETCH_HEAD` was last written
+fn fetch_age(repo: &Repository) -> Option<Duration> {
+    let fetched = match fs::metadata(common_dir(repo).join("FETCH_HEAD")).and_then(|m| m.modified()) {
+        Ok(fetched) => fetched,
+        Err(_) => return None,
+    };
+    Some(
+        SystemTime::now()
+            .duration_since(fetched)
+            .unwrap_or_else(|_| Duration::from_secs(0)),
+    )
+}
+
+fn format_age(age: Duration) -> String {
+    match age.as_secs() as i64 {
+        s if s < 60 => "just now".to_owned(),
+        s => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            format!("{} ago", humanize_duration(now - s, now, 1))
+        }
+    }
+}
+
+/// Files larger than this many bytes are left out of the diff stats
+const DIFF_STAT_MAX_SIZE: i64 = 1024 * 1024;
+
+/// Lines added and removed by the uncommitted changes in the working directory
+struct DiffStats {
+    files: HashMap<PathBuf, (usize, usize)>,
+    added: usize,
+    removed: usize,
+    /// Whether the time budget ran out before every file was counted
+    partial: bool,
+}
+
+impl DiffStats {
+    fn for_file(&self, path: &Path) -> Option<String> {
+        self.files
+            .get(path)
+            .map(|&(added, removed)| format!("+{}/-{}", added, removed))
+    }
+
+    fn total(&self) -> String {
+        format!(
+            "+{}/-{}{}",
+            self.added,
+            self.removed,
+            if self.partial { " (partial)" } else { "" }
+        )
+    }
+}
+
+/// Counts lines changed between `HEAD` and the working directory, giving up
+/// on the remaining files once `budget` has passed
+fn diff_stats(repo: &Repository, budget: Duration) -> Result<DiffStats, Error> {
+    let start = Instant::now();
+    let head = match repo.head().and_then(|h| h.peel_to_tree()) {
+        Ok(tree) => Some(tree),
+        Err(_) => None,
+    };
+    let diff = try!(repo.diff_tree_to_workdir_with_index(
+        head.as_ref(),
+        Some(DiffOptions::new().max_size(DIFF_STAT_MAX_SIZE)),
+    ));
+
+    let mut stats = DiffStats {
+        files: HashMap::new(),
+        added: 0,
+        removed: 0,
+        partial: false,
+    };
+
+    for i in 0..diff.deltas().len() {
+        if start.elapsed() >= budget {
+            stats.partial = true;
+            break;
+        }
+
+        let patch = match Patch::from_diff(&diff, i) {
+            Ok(Some(patch)) => patch,
+            _ => continue,
+        };
+        let (_, added, removed) = try!(patch.line_stats());
+        let delta = patch.delta();
+        if let Some(path) = delta.new_file().path().or(delta.old_file().path()) {
+            let _ = stats.files.insert(path.to_path_buf(), (added, removed));
+        }
+        stats.added += added;
+        stats.removed += removed;
+    }
+
+    Ok(stats)
+}
+
+/// How untracked files are listed in the Git Status section
+#[derive(Clone, Copy, PartialEq)]
+enum Untracked {
+    /// Not at all
+    Off,
+    /// Untracked directories as a single entry
+    Collapsed,
+    /// Every untracked file
+    Full,
+}
+
+impl FromStr for Untracked {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Untracked, ()> {
+        match s {
+            "off" => Ok(Untracked::Off),
+            "collapsed" => Ok(Untracked::Collapsed),
+            "full" => Ok(Untracked::Full),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A repository's `.megaprompt` file and git config, read once per render
+struct Overrides {
+    local: Settings,
+    config: Option<Config>,
+}
+
+impl Overrides {
+    fn load(repo: &Repository) -> Overrides {
+        Overrides {
+            local: repo.workdir()
+                .map(|w| Settings::load_file(&w.join(".megaprompt")))
+                .unwrap_or_default(),
+            config: repo.config().ok(),
+        }
+    }
+
+    /// Looks up a setting the repository can override. Its `.megaprompt` file
+    /// wins over its `megaprompt.*` git config, which wins over `default`.
+    fn get<T: FromStr>(&self, key: &str, git_key: &str, default: T) -> T {
+        let default = match self.config.as_ref().and_then(|c| c.get_string(git_key).ok()) {
+            Some(value) => match value.trim().parse() {
+                Ok(v) => v,
+                Err(_) => {
+                    warn!("Ignoring invalid value {:?} for {}", value, git_key);
+                    default
+                }
+            },
+            None => default,
+        };
+        self.local.get_or(key, default)
+    }
+}
+
+/// Matches `text` against a pattern where `*` stands for any run of
+/// characters and `?` for any single one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some((&'*', rest)) => (0..text.len() + 1).any(|i| matches(rest, &text[i..])),
+            Some((&p, rest)) => match text.split_first() {
+                Some((&t, text)) => (p == '?' || p == t) && matches(rest, text),
+                None => false,
+            },
+        }
+    }
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    matches(&pattern, &text)
+}
+
+/// Whether a commit carries a GPG or SSH signature
+fn is_signed(commit: &Commit) -> bool {
+    commit
+        .raw_header_bytes()
+        .split(|&b| b == b'\n')
+        .any(|line| line.starts_with(b"gpgsig"))
+}
+
+/// Whether the file or directory at `path` takes up more than `limit` bytes.
+/// Stops walking as soon as the limit is passed.
+fn larger_than(path: &Path, limit: u64) -> bool {
+    fn walk(path: &Path, total: &mut u64, limit: u64) {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return,
+        };
+        if metadata.is_dir() {
+            if let Ok(entries) = fs::read_dir(path) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    if *total > limit {
+                        return;
+                    }
+                    walk(&entry.path(), total, limit);
+                }
             }
-            Err(e) => Err(e),
-        },
-        Err(e) => Err(e),
+        } else {
+            *total += metadata.len();
+        }
     }
+
+    let mut total = 0;
+    walk(path, &mut total, limit);
+    total > limit
+}
+
+/// The mtime of an ignored directory, the limit it was checked against and
+/// whether it was over it, by path
+type IgnoredSizes = HashMap<PathBuf, (SystemTime, u64, bool)>;
+
+/// Like `larger_than`, but a directory is only walked again once its mtime
+/// changes. That only happens when its direct entries change, so growth deeper
+/// down shows up late. The results used are moved from `previous` to `current`.
+fn ignored_larger_than(
+    path: &Path,
+    limit: u64,
+    previous: &mut IgnoredSizes,
+    current: &mut IgnoredSizes,
+) -> bool {
+    let mtime = match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => metadata.modified().ok(),
+        _ => return larger_than(path, limit),
+    };
+    let mtime = match mtime {
+        Some(mtime) => mtime,
+        None => return larger_than(path, limit),
+    };
+
+    let over = match previous.remove(path) {
+        Some((seen, seen_limit, over)) if seen == mtime && seen_limit == limit => over,
+        _ => larger_than(path, limit),
+    };
+    let _ = current.insert(path.to_path_buf(), (mtime, limit, over));
+    over
+}
+
+/// Conflicted files larger than this many bytes aren't scanned for markers
+const CONFLICT_SCAN_MAX_SIZE: u64 = 1024 * 1024;
+
+/// The short code and description of a conflict, as `git status` shows them.
+/// `stages` has a bit set for each of the base, our and their index entries.
+fn conflict_kind(stages: u8) -> (&'static str, &'static str) {
+    match stages {
+        0b111 => ("UU", "both modified"),
+        0b110 => ("AA", "both added"),
+        0b101 => ("DU", "deleted by us"),
+        0b011 => ("UD", "deleted by them"),
+        0b010 => ("AU", "added by us"),
+        0b100 => ("UA", "added by them"),
+        _ => ("DD", "both deleted"),
+    }
+}
+
+/// Counts the `<<<<<<<` markers left in a conflicted file
+fn conflict_markers(file: &Path) -> Option<usize> {
+    match fs::metadata(file) {
+        Ok(ref m) if m.is_file() && m.len() <= CONFLICT_SCAN_MAX_SIZE => {}
+        _ => return None,
+    }
+
+    let mut contents = Vec::new();
+    if File::open(file)
+        .and_then(|mut f| f.read_to_end(&mut contents))
+        .is_err()
+    {
+        return None;
+    }
+
+    Some(
+        contents
+            .split(|&b| b == b'\n')
+            .filter(|line| line.starts_with(b"<<<<<<<"))
+            .count(),
+    )
 }
 
 pub struct GitPlugin {
     repo: Option<Repository>,
     path: PathBuf,
+    fetch_warning: Duration,
+    /// Time budget for the per file line counts, if they're enabled
+    diff_stat: Option<Duration>,
+    untracked: Untracked,
+    /// Ignored files over this many megabytes are listed, 0 turns this off
+    ignored_warning: u64,
+    /// Sizes of ignored directories from the last render
+    ignored_sizes: RefCell<IgnoredSizes>,
+    /// Branch to show divergence from, empty to use the remote's default branch
+    base: String,
+    /// Pattern `user.email` has to match, empty to not check it
+    email_pattern: String,
+    /// Whether outgoing commits without a signature are flagged
+    require_signed: bool,
+    /// Most outgoing commits listed, 0 lists all of them
+    outgoing_limit: usize,
+    outgoing_author: bool,
+    outgoing_date: bool,
 }
 
 impl Default for GitPlugin {
     fn default() -> GitPlugin {
+        let settings = Settings::load();
         GitPlugin {
             repo: None,
             path: env::current_dir().expect("There is no current directory!"),
+            fetch_warning: Duration::from_secs(settings.get_or("git.fetch_warning", 24) * 60 * 60),
+            diff_stat: if settings.get_or("git.diff_stat", false) {
+                Some(Duration::from_millis(settings.get_or("git.diff_stat_budget", 50)))
+            } else {
+                None
+            },
+            untracked: settings.get_or("git.untracked", Untracked::Collapsed),
+            ignored_warning: settings.get_or("git.ignored_warning", 0),
+            ignored_sizes: RefCell::new(HashMap::new()),
+            base: settings.get_or("git.base", String::new()),
+            email_pattern: settings.get_or("git.email_pattern", String::new()),
+            require_signed: settings.get_or("git.require_signed", false),
+            outgoing_limit: settings.get_or("git.outgoing_limit", 10),
+            outgoing_author: settings.get_or("git.outgoing_author", false),
+            outgoing_date: settings.get_or("git.outgoing_date", false),
         }
     }
 }
@@ -196,6 +799,18 @@ impl GitPlugin {
         GitPlugin::default()
     }
 
+    /// Overrides the `git.diff_stat` settings, `None` turns the line counts off
+    pub fn set_diff_stat(&mut self, budget: Option<Duration>) {
+        self.diff_stat = budget;
+    }
+
+    /// Overrides the `git.outgoing_*` settings
+    pub fn set_outgoing(&mut self, limit: usize, author: bool, date: bool) {
+        self.outgoing_limit = limit;
+        self.outgoing_author = author;
+        self.outgoing_date = date;
+    }
+
     fn get_repo(&self) -> Result<&Repository, Error> {
         match self.repo {
             Some(ref repo) => Ok(repo),
@@ -203,11 +818,20 @@ impl GitPlugin {
         }
     }
 
+    fn location(&self) -> Location {
+        match self.repo {
+            Some(ref repo) if repo.is_bare() => Location::Bare,
+            Some(ref repo) if self.path.starts_with(repo.path()) => Location::GitDir,
+            _ => Location::WorkDir,
+        }
+    }
+
     fn status(
         &self,
         shell: ShellType,
         buffer: &mut PromptLines,
         path: &Path,
+        overrides: &Overrides,
     ) -> Result<bool, Error> {
         fn file_state_color(state: &StatusTypes) -> u32 {
             match *state {
@@ -221,36 +845,68 @@ impl GitPlugin {
         }
 
         let repo = try!(self.get_repo());
+        let workdir = match repo.workdir() {
+            Some(workdir) => workdir,
+            None => return Ok(false),
+        };
+
+        let untracked = overrides.get("git.untracked", "megaprompt.untracked", self.untracked);
+        let ignored_warning = overrides.get(
+            "git.ignored_warning",
+            "megaprompt.ignoredWarning",
+            self.ignored_warning,
+        );
 
         let st = repo.statuses(Some(
             StatusOptions::new()
-                .include_untracked(true)
+                .include_untracked(untracked != Untracked::Off)
+                .recurse_untracked_dirs(untracked == Untracked::Full)
+                .include_ignored(ignored_warning > 0)
+                .recurse_ignored_dirs(false)
                 .renames_head_to_index(true),
         ));
 
-        let make_path_relative = |current: &Path| {
-            let mut fullpath = repo.workdir()
-                .expect("Repo has no working dir")
-                .to_path_buf();
-            fullpath.push(current);
-            fullpath
-                .make_relative(path)
-                .unwrap_or_else(|| PathBuf::from("/"))
-        };
+        let make_path_relative = |current: &Path| display_path(workdir, current, path);
 
         if let Ok(statuses) = st {
-            if statuses.len() == 0 {
+            let mut previous = self.ignored_sizes.borrow_mut();
+            let mut current = HashMap::new();
+            let large_ignored = statuses
+                .iter()
+                .filter(|s| s.status().contains(git2::Status::IGNORED))
+                .filter_map(|s| s.path().map(PathBuf::from))
+                .filter(|p| {
+                    ignored_larger_than(
+                        &workdir.join(p),
+                        ignored_warning * 1024 * 1024,
+                        &mut previous,
+                        &mut current,
+                    )
+                })
+                .collect::<Vec<_>>();
+            *previous = current;
+
+            // Conflicts get their own section
+            let entries = statuses
+                .iter()
+                .filter(|s| {
+                    !s.status()
+                        .intersects(git2::Status::CONFLICTED | git2::Status::IGNORED)
+                })
+                .collect::<Vec<_>>();
+            if entries.is_empty() && large_ignored.is_empty() {
                 return Ok(false);
             }
 
-            buffer.push(
-                shell
-                    .new_line()
-                    .colored_block("Git Status", color::CYAN)
-                    .build(),
-            );
+            let stats = self.diff_stat.and_then(|budget| diff_stats(repo, budget).ok());
 
-            for stat in statuses.iter() {
+            let mut header = shell.new_line().colored_block("Git Status", color::CYAN);
+            if let Some(ref stats) = stats {
+                header = header.colored_block(stats.total(), color::WHITE);
+            }
+            buffer.push(header.build());
+
+            for stat in entries {
                 let mut line = shell.new_free_line();
 
                 let status = GitStatus::new(stat.status());
@@ -266,12 +922,12 @@ impl GitPlugin {
                 let val = format!(
                     "{} {}",
                     status,
-                    match diff {
-                        Some(delta) => {
-                            let old =
-                                make_path_relative(delta.old_file().path().expect("no old file"));
-                            let new =
-                                make_path_relative(delta.new_file().path().expect("no new file"));
+                    match diff.as_ref()
+                        .map(|delta| (delta.old_file().path(), delta.new_file().path()))
+                    {
+                        Some((Some(old), Some(new))) => {
+                            let old = make_path_relative(old);
+                            let new = make_path_relative(new);
 
                             if old == new {
                                 format!("{}", old.display())
@@ -279,10 +935,7 @@ impl GitPlugin {
                                 format!("{} -> {}", old.display(), new.display())
                             }
                         }
-                        None => format!(
-                            "{}",
-                            Path::new(stat.path().expect("No status path")).display()
-                        ),
+                        _ => format!("{}", String::from_utf8_lossy(stat.path_bytes())),
                     }
                 );
 
@@ -298,117 +951,563 @@ impl GitPlugin {
                     },
                 };
 
+                if let Some(stat) = stats
+                    .as_ref()
+                    .and_then(|s| stat.path().and_then(|p| s.for_file(Path::new(p))))
+                {
+                    line = line.colored_block(stat, color::WHITE);
+                }
+
                 buffer.push(line.indent().build());
             }
 
+            for ignored in large_ignored {
+                let full = workdir.join(&ignored);
+                buffer.push(
+                    shell
+                        .new_free_line()
+                        .indent()
+                        .colored_block(
+                            format!(
+                                "!! {}{} over {} MB",
+                                make_path_relative(&ignored).display(),
+                                if full.is_dir() { "/" } else { "" },
+                                ignored_warning
+                            ),
+                            color::YELLOW,
+                        )
+                        .build(),
+                );
+            }
+
             return Ok(true);
         } else {
             return Ok(false);
         }
     }
 
-    fn outgoing(
+    fn conflicts(
         &self,
         shell: ShellType,
         buffer: &mut PromptLines,
-        has_status: bool,
+        path: &Path,
+        indented: bool,
     ) -> Result<bool, Error> {
         let repo = try!(self.get_repo());
+        let workdir = match repo.workdir() {
+            Some(workdir) => workdir,
+            None => return Ok(false),
+        };
 
-        let branches = try!(git_branch(repo));
+        let index = try!(repo.index());
+        if !index.has_conflicts() {
+            return Ok(false);
+        }
 
-        let mut revwalk = try!(repo.revwalk());
+        let mut conflicts = BTreeMap::new();
+        for entry in index.iter() {
+            let stage = (entry.flags >> 12) & 0x3;
+            if stage > 0 {
+                *conflicts.entry(entry.path).or_insert(0u8) |= 1 << (stage - 1);
+            }
+        }
 
-        let from = try!(
-            repo.revparse_single(
-                branches
-                    .upstream
-                    .unwrap_or_else(|| "HEAD".to_owned())
-                    .as_ref()
-            )
-        ).id();
-        let to = try!(repo.revparse_single(
-            branches.name.unwrap_or_else(|| "HEAD".to_owned()).as_ref()
-        )).id();
+        buffer.push(
+            shell
+                .new_line()
+                .colored_block("Git Conflicts", color::CYAN)
+                .indent_by(if indented { 1 } else { 0 })
+                .build(),
+        );
 
-        try!(revwalk.push(to));
-        try!(revwalk.hide(from));
+        for (file, stages) in conflicts {
+            let file = PathBuf::from(String::from_utf8_lossy(&file).into_owned());
+            let (code, kind) = conflict_kind(stages);
+
+            let mut line = shell
+                .new_free_line()
+                .indent()
+                .bold_colored_block(
+                    format!("{} {}", code, display_path(workdir, &file, path).display()),
+                    color::RED,
+                )
+                .colored_block(kind, color::WHITE);
+
+            line = match conflict_markers(&workdir.join(&file)) {
+                Some(0) => line.colored_block("no markers", color::GREEN),
+                Some(1) => line.colored_block("1 marker", color::RED),
+                Some(n) => line.colored_block(format!("{} markers", n), color::RED),
+                None => line,
+            };
+
+            buffer.push(line.build());
+        }
+
+        Ok(true)
+    }
+
+    fn submodules(
+        &self,
+        shell: ShellType,
+        buffer: &mut PromptLines,
+        path: &Path,
+        indented: bool,
+    ) -> Result<bool, Error> {
+        let repo = try!(self.get_repo());
+        let workdir = match repo.workdir() {
+            Some(workdir) => workdir,
+            None => return Ok(false),
+        };
 
-        let mut log_shown = false;
+        let mut shown = false;
 
-        for possible_id in revwalk {
-            let id = match possible_id {
-                Ok(id) => id,
+        for submodule in try!(repo.submodules()) {
+            let name = match submodule.name() {
+                Some(name) => name,
+                None => continue,
+            };
+            let status = match repo.submodule_status(name, SubmoduleIgnore::Unspecified) {
+                Ok(status) => status,
                 Err(_) => continue,
             };
 
-            let commit = try!(repo.find_commit(id));
+            let (state, col) = if status.contains(SubmoduleStatus::WD_UNINITIALIZED) {
+                ("uninitialized".to_owned(), color::YELLOW)
+            } else if status.contains(SubmoduleStatus::WD_MODIFIED) {
+                (
+                    match (submodule.workdir_id(), submodule.index_id()) {
+                        (Some(wd), Some(index)) => format!(
+                            "checked out {}, recorded {}",
+                            short_id(wd),
+                            short_id(index)
+                        ),
+                        _ => "checked out commit differs".to_owned(),
+                    },
+                    color::RED,
+                )
+            } else if status.intersects(
+                SubmoduleStatus::WD_INDEX_MODIFIED | SubmoduleStatus::WD_WD_MODIFIED
+                    | SubmoduleStatus::WD_UNTRACKED,
+            ) {
+                ("dirty".to_owned(), color::BLUE)
+            } else {
+                continue;
+            };
 
-            if !log_shown {
+            if !shown {
                 buffer.push(
                     shell
                         .new_line()
-                        .colored_block("Git Outgoing", color::CYAN)
-                        .indent_by(if has_status { 1 } else { 0 })
+                        .colored_block("Git Submodules", color::CYAN)
+                        .indent_by(if indented { 1 } else { 0 })
                         .build(),
                 );
-                log_shown = true;
+                shown = true;
             }
 
             buffer.push(
                 shell
                     .new_free_line()
                     .indent()
-                    .block(format!(
-                        "{}{} {}",
-                        shell.reset(),
-                        String::from_utf8_lossy(
-                            try!(try!(repo.find_object(commit.id(), None)).short_id()).deref()
+                    .colored_block(
+                        format!(
+                            "{}: {}",
+                            display_path(workdir, submodule.path(), path).display(),
+                            state
                         ),
-                        String::from_utf8_lossy(match commit.summary_bytes() {
-                            Some(b) => b,
-                            None => continue,
-                        })
-                    ))
+                        col,
+                    )
                     .build(),
             );
         }
 
-        Ok(log_shown)
+        Ok(shown)
     }
 
-    fn end(
+    /// The name of the linked worktree the prompt is in, if any
+    fn worktree_name(&self) -> Option<String> {
+        match self.repo {
+            Some(ref repo) if repo.is_worktree() => repo.path()
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned()),
+            _ => None,
+        }
+    }
+
+    fn worktrees(
         &self,
         shell: ShellType,
         buffer: &mut PromptLines,
+        path: &Path,
         indented: bool,
     ) -> Result<bool, Error> {
         let repo = try!(self.get_repo());
+        let workdir = match repo.workdir() {
+            Some(workdir) => workdir,
+            None => return Ok(false),
+        };
 
-        let branches = try!(git_branch(repo));
+        let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+        let current = canonical(workdir);
+        let others = worktrees(repo)
+            .into_iter()
+            .filter(|wt| canonical(&wt.path) != current)
+            .collect::<Vec<_>>();
+        if others.is_empty() {
+            return Ok(false);
+        }
 
         buffer.push(
             shell
                 .new_line()
-                .colored_block(
-                    &match (branches.name, branches.upstream) {
-                        (None, None) => "New Repository".to_owned(),
-                        (Some(name), None) => name,
-                        (Some(name), Some(remote)) => format!(
-                            "{}{} -> {}{}",
-                            name,
-                            shell.reset(),
-                            shell.col(color::MAGENTA),
-                            remote
-                        ),
-                        _ => "Unknown branch state".to_owned(),
-                    },
-                    color::CYAN,
-                )
+                .colored_block("Git Worktrees", color::CYAN)
                 .indent_by(if indented { 1 } else { 0 })
                 .build(),
         );
 
+        for wt in others {
+            let location = if wt.path.exists() {
+                wt.path
+                    .clone()
+                    .make_relative(path)
+                    .unwrap_or_else(|| wt.path.clone())
+                    .display()
+                    .to_string()
+            } else {
+                "missing".to_owned()
+            };
+
+            buffer.push(
+                shell
+                    .new_free_line()
+                    .indent()
+                    .colored_block(
+                        format!("{}: {} ({})", wt.name, wt.head, location),
+                        color::WHITE,
+                    )
+                    .build(),
+            );
+        }
+
+        Ok(true)
+    }
+
+    fn outgoing(
+        &self,
+        shell: ShellType,
+        buffer: &mut PromptLines,
+        has_status: bool,
+        overrides: &Overrides,
+    ) -> Result<bool, Error> {
+        let repo = try!(self.get_repo());
+
+        let branches = try!(git_branch(repo, false));
+
+        let mut revwalk = try!(repo.revwalk());
+
+        let from = try!(repo.revparse_single(match branches.upstream {
+            Upstream::Tracking(ref upstream) => upstream,
+            _ => "HEAD",
+        })).id();
+        let to = try!(repo.revparse_single("HEAD")).id();
+
+        try!(revwalk.push(to));
+        try!(revwalk.hide(from));
+        let _ = revwalk.set_sorting(Sort::TOPOLOGICAL);
+
+        let ids = revwalk.filter_map(|id| id.ok()).collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Ok(false);
+        }
+
+        let shown = if self.outgoing_limit == 0 {
+            ids.len()
+        } else {
+            cmp::min(self.outgoing_limit, ids.len())
+        };
+
+        let mut commits = Vec::with_capacity(shown);
+        for id in &ids[..shown] {
+            commits.push(try!(repo.find_commit(*id)));
+        }
+
+        // Commits reached by following first parents from HEAD, the rest came
+        // in through merges. Merges come before the commits they bring in, so
+        // there are none to show unless a shown commit is a merge.
+        let has_merges = commits.iter().any(|c| c.parent_ids().count() > 1);
+        let mainline = if has_merges {
+            let mut first_parents = try!(repo.revwalk());
+            try!(first_parents.push(to));
+            try!(first_parents.hide(from));
+            first_parents.simplify_first_parent();
+            first_parents.filter_map(|id| id.ok()).collect::<HashSet<_>>()
+        } else {
+            HashSet::new()
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let require_signed = overrides.get(
+            "git.require_signed",
+            "megaprompt.requireSigned",
+            self.require_signed,
+        );
+
+        let authors = commits
+            .iter()
+            .map(|c| String::from_utf8_lossy(c.author().name_bytes()).into_owned())
+            .collect::<Vec<_>>();
+        let dates = commits
+            .iter()
+            .map(|c| format_age(Duration::from_secs(cmp::max(now - c.time().seconds(), 0) as u64)))
+            .collect::<Vec<_>>();
+        let author_width = authors.iter().map(|a| a.chars().count()).max().unwrap_or(0);
+        let date_width = dates.iter().map(|d| d.chars().count()).max().unwrap_or(0);
+
+        buffer.push(
+            shell
+                .new_line()
+                .colored_block("Git Outgoing", color::CYAN)
+                .indent_by(if has_status { 1 } else { 0 })
+                .build(),
+        );
+
+        for (i, commit) in commits.iter().enumerate() {
+            let mut text = format!("{}", shell.reset());
+
+            if has_merges {
+                text.push_str(if !mainline.contains(&commit.id()) {
+                    "│● "
+                } else if commit.parent_ids().count() > 1 {
+                    "●╮ "
+                } else {
+                    "●  "
+                });
+            }
+
+            text.push_str(&String::from_utf8_lossy(
+                try!(try!(repo.find_object(commit.id(), None)).short_id()).deref(),
+            ));
+
+            if self.outgoing_date {
+                text.push_str(&format!(" {:<width$}", dates[i], width = date_width));
+            }
+            if self.outgoing_author {
+                text.push_str(&format!(" {:<width$}", authors[i], width = author_width));
+            }
+
+            text.push(' ');
+            text.push_str(&String::from_utf8_lossy(commit.summary_bytes().unwrap_or(b"")));
+
+            let mut line = shell.new_free_line().indent().block(text);
+            if require_signed && !is_signed(commit) {
+                line = line.bold_colored_block("unsigned", color::RED);
+            }
+            buffer.push(line.build());
+        }
+
+        if shown < ids.len() {
+            buffer.push(
+                shell
+                    .new_free_line()
+                    .indent()
+                    .block(format!("{}… and {} more", shell.reset(), ids.len() - shown))
+                    .build(),
+            );
+        }
+
+        Ok(true)
+    }
+
+    fn end(
+        &self,
+        speed: PluginSpeed,
+        shell: ShellType,
+        buffer: &mut PromptLines,
+        indented: bool,
+        overrides: &Overrides,
+    ) -> Result<bool, Error> {
+        let repo = try!(self.get_repo());
+        let slow = match speed {
+            PluginSpeed::Slow => true,
+            _ => false,
+        };
+
+        let branches = try!(git_branch(repo, slow));
+
+        let mut line = shell.new_line();
+        if let Some(name) = self.worktree_name() {
+            line = line.colored_block(format!("worktree {}", name), color::YELLOW);
+        }
+
+        let tracking = match branches.upstream {
+            Upstream::Tracking(_) | Upstream::Gone(_) => true,
+            _ => false,
+        };
+        // Walking to the base branch can take a while on long-lived branches
+        let vs_base = if branches.unborn || !slow {
+            None
+        } else {
+            self.vs_base(repo, overrides, &branches.upstream, branches.name.as_ref())
+        };
+        let name = branches
+            .name
+            .unwrap_or_else(|| "Unknown branch state".to_owned());
+        line = if branches.unborn {
+            line.colored_block(name, color::CYAN)
+                .colored_block("New Repository", color::WHITE)
+        } else {
+            match branches.upstream {
+                Upstream::Tracking(remote) => line.colored_block(
+                    format!(
+                        "{}{} -> {}{}",
+                        name,
+                        shell.reset(),
+                        shell.col(color::MAGENTA),
+                        remote
+                    ),
+                    color::CYAN,
+                ),
+                Upstream::Gone(remote) => line.colored_block(
+                    format!(
+                        "{}{} -> {}{} (gone)",
+                        name,
+                        shell.reset(),
+                        shell.col(color::RED),
+                        remote
+                    ),
+                    color::CYAN,
+                ),
+                Upstream::NotConfigured(remote) => {
+                    let hint = format!("no upstream, push -u {} {}", remote, name);
+                    line.colored_block(name, color::CYAN)
+                        .colored_block(hint, color::YELLOW)
+                }
+                Upstream::LocalOnly => line.colored_block(name, color::CYAN)
+                    .colored_block("local only", color::WHITE),
+                Upstream::Detached => line.colored_block(name, color::YELLOW),
+            }
+        };
+
+        if let Some(warning) = self.identity_warning(overrides) {
+            line = line.bold_colored_block(warning, color::RED);
+        }
+
+        if !branches.tags.is_empty() {
+            line = line.colored_block(format!("tag {}", branches.tags.join(", ")), color::GREEN);
+        }
+
+        if let Some(vs_base) = vs_base {
+            line = line.colored_block(vs_base, color::BLUE);
+        }
+
+        if tracking {
+            if let Some(age) = fetch_age(repo) {
+                let text = format!("fetched {}", format_age(age));
+                line = if age >= self.fetch_warning {
+                    line.bold_colored_block(text, color::RED)
+                } else {
+                    line.colored_block(text, color::WHITE)
+                };
+            }
+        }
+
+        buffer.push(
+            line.indent_by(if indented { 1 } else { 0 })
+                .build(),
+        );
+
+        Ok(true)
+    }
+
+    /// Complains when `user.email` doesn't match the repository's pattern
+    fn identity_warning(&self, overrides: &Overrides) -> Option<String> {
+        let pattern = overrides.get(
+            "git.email_pattern",
+            "megaprompt.emailPattern",
+            self.email_pattern.clone(),
+        );
+        if pattern.is_empty() {
+            return None;
+        }
+
+        match overrides.config.as_ref().and_then(|c| c.get_string("user.email").ok()) {
+            Some(ref email) if glob_match(&pattern, email) => None,
+            Some(email) => Some(format!("{} is not {}", email, pattern)),
+            None => Some(format!("no user.email, expected {}", pattern)),
+        }
+    }
+
+    /// Describes how far `HEAD` has drifted from the base branch, unless it is
+    /// on the base branch
+    fn vs_base(
+        &self,
+        repo: &Repository,
+        overrides: &Overrides,
+        upstream: &Upstream,
+        name: Option<&String>,
+    ) -> Option<String> {
+        let base = overrides.get("git.base", "megaprompt.base", self.base.clone());
+        let base = match base_branch(repo, &base) {
+            Some(base) => base,
+            None => return None,
+        };
+
+        if let Upstream::Tracking(ref upstream) = *upstream {
+            if *upstream == base {
+                return None;
+            }
+        }
+        let label = match tracked_remote(repo) {
+            Some(ref remote) if base.starts_with(&format!("{}/", remote)) => {
+                base[remote.len() + 1..].to_owned()
+            }
+            _ => base.clone(),
+        };
+        if name.map_or(false, |name| *name == label || *name == base) {
+            return None;
+        }
+
+        match divergence(repo, &base) {
+            Ok((ahead, behind)) => Some(format!("{} +{}/-{}", label, ahead, behind)),
+            Err(_) => None,
+        }
+    }
+
+    /// Shows the branch line for places without a working directory, where
+    /// there is no status and nothing to compare outgoing commits against
+    fn end_without_workdir(
+        &self,
+        speed: PluginSpeed,
+        shell: ShellType,
+        buffer: &mut PromptLines,
+    ) -> Result<bool, Error> {
+        let repo = try!(self.get_repo());
+        let slow = match speed {
+            PluginSpeed::Slow => true,
+            _ => false,
+        };
+
+        let head = git_branch(repo, slow)
+            .ok()
+            .and_then(|b| if b.unborn { None } else { b.name })
+            .unwrap_or_else(|| "New Repository".to_owned());
+
+        let line = match self.location() {
+            Location::Bare => shell
+                .new_line()
+                .colored_block("bare", color::YELLOW)
+                .colored_block(head, color::CYAN)
+                .block(try!(refs_summary(repo))),
+            _ => shell
+                .new_line()
+                .bold_colored_block("GIT_DIR", color::RED)
+                .colored_block(head, color::CYAN),
+        };
+        buffer.push(line.build());
+
         Ok(true)
     }
 }
@@ -426,15 +1525,40 @@ impl PromptBufferPlugin for GitPlugin {
             self.repo = get_git(&self.path);
         }
 
-        let st = match speed {
+        match self.location() {
+            Location::Bare | Location::GitDir => {
+                let _ = self.end_without_workdir(speed, shell, lines).ok();
+                return;
+            }
+            Location::WorkDir => {}
+        }
+
+        let overrides = match self.get_repo() {
+            Ok(repo) => Overrides::load(repo),
+            Err(_) => return,
+        };
+
+        let (st, sm) = match speed {
             PluginSpeed::Slow => {
                 trace!("Finding git status");
-                self.status(shell, lines, path).ok().unwrap_or(false)
+                let st = self.status(shell, lines, path, &overrides).ok().unwrap_or(false);
+                trace!("Finding conflicts");
+                let st = self.conflicts(shell, lines, path, st).ok().unwrap_or(false) || st;
+                trace!("Finding submodule status");
+                let sm = self.submodules(shell, lines, path, st).ok().unwrap_or(false);
+                (st, sm)
             }
-            _ => false,
+            _ => (false, false),
         };
+        trace!("Finding worktrees");
+        let wt = self.worktrees(shell, lines, path, st || sm)
+            .ok()
+            .unwrap_or(false);
         trace!("Finding outgoing commits");
-        let out = self.outgoing(shell, lines, st).ok().unwrap_or(false);
-        let _ = self.end(shell, lines, st || out).ok();
+        let out = self.outgoing(shell, lines, st || sm || wt, &overrides)
+            .ok()
+            .unwrap_or(false);
+        let _ = self.end(speed, shell, lines, st || sm || wt || out, &overrides)
+            .ok();
     }
 }
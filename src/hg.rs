@@ -0,0 +1,263 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Shows the state of Mercurial repositories, laid out like `GitPlugin`.
+//!
+//! The branch and bookmark are read straight from `.hg`, so the branch line is
+//! cheap enough for fast prompts. Status and outgoing changesets come from
+//! running `hg`, which is killed if it doesn't answer within `hg.timeout`.
+//! Outgoing changesets are the draft ones, since hg makes changesets public
+//! once they are pushed, so no remote is contacted.
+
+use prompt_buffer::{PluginSpeed, PromptBufferPlugin, PromptLines, ShellType};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use term::color;
+
+use settings::Settings;
+
+/// Finds the root of the Mercurial repository containing `path`
+fn find_root(path: &Path) -> Option<PathBuf> {
+    let mut dir = Some(path);
+    while let Some(d) = dir {
+        if d.join(".hg").is_dir() {
+            return Some(d.to_path_buf());
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let mut contents = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+        Ok(_) => Some(contents.trim().to_owned()),
+        Err(_) => None,
+    }
+}
+
+fn status_color(code: char) -> u32 {
+    match code {
+        'M' => color::BLUE,
+        'A' => color::GREEN,
+        'R' | '!' => color::RED,
+        _ => color::WHITE,
+    }
+}
+
+pub struct HgPlugin {
+    command: String,
+    timeout: Duration,
+}
+
+impl Default for HgPlugin {
+    fn default() -> HgPlugin {
+        let settings = Settings::load();
+        HgPlugin {
+            command: settings.get_or("hg.command", "hg".to_owned()),
+            timeout: Duration::from_millis(settings.get_or("hg.timeout", 1000)),
+        }
+    }
+}
+
+impl HgPlugin {
+    pub fn new() -> HgPlugin {
+        HgPlugin::default()
+    }
+
+    /// Overrides the `hg.command` setting
+    pub fn set_command<S: Into<String>>(&mut self, command: S) {
+        self.command = command.into();
+    }
+
+    /// Overrides the `hg.timeout` setting
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Runs `hg` in `root`, returning its output if it succeeds in time
+    fn hg(&self, root: &Path, args: &[&str]) -> Option<String> {
+        let mut child = match Command::new(&self.command)
+            .args(args)
+            .current_dir(root)
+            .env("HGPLAIN", "1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                warn!("Unable to run {}: {}", self.command, e);
+                return None;
+            }
+        };
+
+        // Read on another thread so a full pipe can't stall the child
+        let mut stdout = match child.stdout.take() {
+            Some(stdout) => stdout,
+            None => return None,
+        };
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stdout.read_to_string(&mut output);
+            output
+        });
+
+        let start = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if start.elapsed() < self.timeout => {
+                    thread::sleep(Duration::from_millis(5))
+                }
+                _ => {
+                    warn!("{} {:?} took too long", self.command, args);
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return None;
+                }
+            }
+        };
+
+        match reader.join() {
+            Ok(output) => if status.success() {
+                Some(output)
+            } else {
+                None
+            },
+            Err(_) => None,
+        }
+    }
+
+    fn status(&self, shell: ShellType, buffer: &mut PromptLines, root: &Path) -> bool {
+        let output = match self.hg(root, &["status"]) {
+            Some(output) => output,
+            None => return false,
+        };
+        if output.trim().is_empty() {
+            return false;
+        }
+
+        buffer.push(
+            shell
+                .new_line()
+                .colored_block("Hg Status", color::CYAN)
+                .build(),
+        );
+
+        for entry in output.lines() {
+            let code = entry.chars().next().unwrap_or(' ');
+            buffer.push(
+                shell
+                    .new_free_line()
+                    .indent()
+                    .colored_block(entry, status_color(code))
+                    .build(),
+            );
+        }
+
+        true
+    }
+
+    fn outgoing(
+        &self,
+        shell: ShellType,
+        buffer: &mut PromptLines,
+        root: &Path,
+        indented: bool,
+    ) -> bool {
+        let output = match self.hg(
+            root,
+            &[
+                "log",
+                "-r",
+                "draft() and ::.",
+                "-T",
+                "{node|short} {desc|firstline}\\n",
+            ],
+        ) {
+            Some(output) => output,
+            None => return false,
+        };
+        if output.trim().is_empty() {
+            return false;
+        }
+
+        buffer.push(
+            shell
+                .new_line()
+                .colored_block("Hg Outgoing", color::CYAN)
+                .indent_by(if indented { 1 } else { 0 })
+                .build(),
+        );
+
+        // hg log lists oldest first, the git section lists newest first
+        for changeset in output.lines().rev() {
+            buffer.push(
+                shell
+                    .new_free_line()
+                    .indent()
+                    .block(format!("{}{}", shell.reset(), changeset))
+                    .build(),
+            );
+        }
+
+        true
+    }
+
+    fn end(&self, shell: ShellType, buffer: &mut PromptLines, root: &Path, indented: bool) {
+        let hg = root.join(".hg");
+        let branch = read_trimmed(&hg.join("branch"))
+            .and_then(|b| if b.is_empty() { None } else { Some(b) })
+            .unwrap_or_else(|| "default".to_owned());
+
+        let mut line = shell
+            .new_line()
+            .colored_block("hg", color::WHITE)
+            .colored_block(branch, color::CYAN);
+        if let Some(bookmark) = read_trimmed(&hg.join("bookmarks.current")) {
+            if !bookmark.is_empty() {
+                line = line.colored_block(format!("@{}", bookmark), color::MAGENTA);
+            }
+        }
+
+        buffer.push(line.indent_by(if indented { 1 } else { 0 }).build());
+    }
+}
+
+impl PromptBufferPlugin for HgPlugin {
+    fn run(
+        &mut self,
+        speed: PluginSpeed,
+        shell: ShellType,
+        path: &PathBuf,
+        lines: &mut PromptLines,
+    ) {
+        let root = match find_root(path) {
+            Some(root) => root,
+            None => return,
+        };
+
+        let (st, out) = match speed {
+            PluginSpeed::Slow => {
+                trace!("Finding hg status");
+                let st = self.status(shell, lines, &root);
+                trace!("Finding hg outgoing changesets");
+                let out = self.outgoing(shell, lines, &root, st);
+                (st, out)
+            }
+            _ => (false, false),
+        };
+        self.end(shell, lines, &root, st || out);
+    }
+}
@@ -0,0 +1,26 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The plugins and supporting code behind the `megaprompt` binary
+
+#![deny(unused_allocation, unused_attributes, unused_features, unused_import_braces,
+        unused_parens, unused_must_use, stable_features, bad_style, unused)]
+
+extern crate git2;
+#[macro_use]
+extern crate log;
+extern crate prompt_buffer;
+extern crate term;
+extern crate time;
+
+pub mod cache;
+pub mod fetch;
+pub mod settings;
+pub mod git;
+pub mod hg;
+pub mod due_date;
@@ -13,21 +13,21 @@
 #[macro_use]
 extern crate chan;
 extern crate clap;
-extern crate git2;
 extern crate log4rs;
 #[macro_use]
 extern crate log;
+extern crate megaprompt;
 extern crate num;
 extern crate prompt_buffer;
-extern crate term;
 extern crate time;
 extern crate unix_socket;
 
-use prompt_buffer::{PromptBuffer, PromptThread, ShellType};
+use prompt_buffer::{PluginSpeed, PromptBuffer, PromptThread, ShellType};
 
 use std::collections::HashMap;
 use std::fs;
 use std::io::{Read, Write};
+use std::str::FromStr;
 
 use time::Duration;
 use log4rs::config;
@@ -42,15 +42,19 @@ use std::thread;
 use chan::Receiver;
 use std::os::unix::fs::MetadataExt;
 use std::process::Command;
-use clap::{App, Arg, ArgGroup};
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
 
-mod git;
-mod due_date;
+use megaprompt::{due_date, git, hg};
+use megaprompt::due_date::manage;
+use megaprompt::cache::PromptCache;
+use megaprompt::fetch::AutoFetch;
+use megaprompt::settings::Settings;
 
 fn get_prompt(shell: ShellType) -> PromptBuffer {
     let mut buf = PromptBuffer::new(shell);
     buf.add_plugin(due_date::DueDatePlugin::new());
     buf.add_plugin(git::GitPlugin::new());
+    buf.add_plugin(hg::HgPlugin::new());
 
     buf
 }
@@ -78,6 +82,7 @@ macro_rules! sock_try {
 enum RunMode {
     Daemon,
     Main,
+    Wait,
     Test,
 }
 
@@ -106,12 +111,76 @@ fn main() {
                 .long("zsh")
                 .help("Get output for zsh"),
         )
+        .arg(
+            Arg::with_name("wait")
+                .short("w")
+                .long("wait")
+                .conflicts_with("daemon")
+                .help("Wait for the result of a prompt that was too slow to render"),
+        )
         .group(
             ArgGroup::with_name("mode")
                 .args(&["daemon", "bash", "zsh"])
                 .required(true),
         )
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("due")
+                .about("Manage the deadlines in .due files")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Set a deadline in the .due file of the current directory")
+                        .arg(
+                            Arg::with_name("date")
+                                .required(true)
+                                .help("When it is due, e.g. \"2026-11-01 17:00\" or \"next friday\""),
+                        )
+                        .arg(
+                            Arg::with_name("title")
+                                .long("title")
+                                .takes_value(true)
+                                .help("What is due"),
+                        )
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .takes_value(true)
+                                .help("The section to set, to keep several deadlines in one file"),
+                        )
+                        .arg(
+                            Arg::with_name("repeat")
+                                .long("repeat")
+                                .takes_value(true)
+                                .help("Repeat from the date, e.g. weekly, \"monthly on 15\" or \"cron 0 15 * * 5\""),
+                        )
+                        .arg(
+                            Arg::with_name("no-repeat")
+                                .long("no-repeat")
+                                .conflicts_with("repeat")
+                                .help("Stop repeating"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the deadlines of the current directory and its parents"),
+                )
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Remove the .due file of the current directory")
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .takes_value(true)
+                                .help("Only remove this section"),
+                        ),
+                ),
+        )
         .get_matches();
+    if let Some(due) = matches.subcommand_matches("due") {
+        do_due(due);
+        return;
+    }
     let daemon = matches.is_present("daemon");
     let shell = if matches.is_present("bash") {
         ShellType::Bash
@@ -121,6 +190,8 @@ fn main() {
     run(
         if daemon {
             RunMode::Daemon
+        } else if matches.is_present("wait") {
+            RunMode::Wait
         } else {
             RunMode::Main
         },
@@ -128,10 +199,112 @@ fn main() {
     )
 }
 
-fn do_daemon(socket_path: &Path) {
+fn do_due(matches: &ArgMatches) {
+    let dir = env::current_dir().expect("There is no current dir");
+    let now = time::now();
+
+    let result = match matches.subcommand() {
+        ("set", Some(set)) => manage::set(
+            &dir,
+            set.value_of("name"),
+            set.value_of("date").expect("The date is required"),
+            set.value_of("title"),
+            if set.is_present("no-repeat") {
+                Some(None)
+            } else {
+                set.value_of("repeat").map(Some)
+            },
+            &now,
+        ).map(|at| {
+            println!(
+                "Due {} ({})",
+                manage::format_iso(at),
+                describe(at.sec, now.to_timespec().sec)
+            )
+        }),
+        ("list", _) => {
+            for (file, due_file) in manage::list(&dir) {
+                println!("{}", file.display());
+                let written = due_date::written(&file, &now);
+                match due_file {
+                    Ok(due_file) => for deadline in due_file.deadlines {
+                        let due = match due_date::resolve(&deadline, &written, &now) {
+                            Ok(at) => describe(at.sec, now.to_timespec().sec),
+                            Err(e) => e,
+                        };
+                        let name = deadline
+                            .name
+                            .as_ref()
+                            .map(|n| format!("[{}] ", n))
+                            .unwrap_or_default();
+                        let when = match deadline.repeat {
+                            Some(ref repeat) if deadline.due.is_empty() => repeat.clone(),
+                            Some(ref repeat) => format!("{}, {}", deadline.due, repeat),
+                            None => deadline.due.clone(),
+                        };
+                        println!("  {}{}: {} ({})", name, deadline.title, when, due);
+                    },
+                    Err(e) => println!("  {}", e),
+                }
+            }
+            Ok(())
+        }
+        ("clear", Some(clear)) => manage::clear(&dir, clear.value_of("name"), &now).map(|removed| {
+            println!(
+                "Removed {} deadline{}",
+                removed,
+                if removed == 1 { "" } else { "s" }
+            )
+        }),
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("megaprompt: {}", e);
+        ::std::process::exit(1);
+    }
+}
+
+/// Describes a point in time relative to `now` for the `due` subcommand
+fn describe(at: i64, now: i64) -> String {
+    let span = prompt_buffer::humanize_duration(now, at, 2);
+    if at < now {
+        format!("{} ago", span)
+    } else {
+        format!("in {}", span)
+    }
+}
+
+fn parse_request(request: &str) -> (PathBuf, ShellType, bool) {
+    let (request, wait) = if request.starts_with("!wait ") {
+        (&request[6..], true)
+    } else {
+        (request, false)
+    };
+
+    if request.starts_with("!2 ") {
+        let parts = request.split(" ").collect::<Vec<_>>();
+        let output = PathBuf::from(&parts[1]);
+        let shell = match parts[2] {
+            "Bash" => ShellType::Bash,
+            "Zsh" => ShellType::Zsh,
+            _ => ShellType::Bash,
+        };
+        (output, shell, wait)
+    } else {
+        (PathBuf::from(&request), ShellType::Bash, wait)
+    }
+}
+
+fn do_daemon(socket_path: &Path, settings: &Settings) {
+    // Only string settings, which can't be invalid and warn, are read before
+    // the logger exists
     let main_log = FileAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{h({f:>30.30}: {m}{n})}")))
-        .build("/var/log/megaprompt/current.out")
+        .build(settings.get_or(
+            "daemon.log",
+            "/var/log/megaprompt/current.out".to_owned(),
+        ))
         .expect("Unable to create file appender");
 
     let config = config::Config::builder()
@@ -145,6 +318,16 @@ fn do_daemon(socket_path: &Path) {
 
     log4rs::init_config(config).expect("Unable to init logger");
 
+    let thread_timeout: u64 = settings.get_or("daemon.timeout", 50);
+    let cache = PromptCache::new();
+    let fetch = match settings.get_or("daemon.fetch_interval", 0) {
+        0 => None,
+        minutes => Some(AutoFetch::new(
+            ::std::time::Duration::from_secs(minutes * 60),
+            settings.get_or("daemon.fetch_jobs", 2),
+            ::std::time::Duration::from_secs(settings.get_or("daemon.fetch_timeout", 300)),
+        )),
+    };
     let last_modified = exe_changed();
     let mut threads: HashMap<(PathBuf, ShellType), PromptThread> = HashMap::new();
 
@@ -167,24 +350,17 @@ fn do_daemon(socket_path: &Path) {
 
         let mut output = String::new();
         let _ = sock_try!(c.read_to_string(&mut output));
-        let (output, shell) = if output.starts_with("!2 ") {
-            let parts = output.split(" ").collect::<Vec<_>>();
-            let output = PathBuf::from(&parts[1]);
-            let shell = match parts[2] {
-                "Bash" => ShellType::Bash,
-                "Zsh" => ShellType::Zsh,
-                _ => ShellType::Bash,
-            };
-            (output, shell)
-        } else {
-            (PathBuf::from(&output), ShellType::Bash)
-        };
+        let (output, shell, wait) = parse_request(&output);
         info!(
             "Preparing to respond to for {} [{:?}]",
             output.display(),
             shell
         );
 
+        if let Some(ref fetch) = fetch {
+            fetch.watch(&output);
+        }
+
         let keys: Vec<(PathBuf, ShellType)> = threads.keys().cloned().collect();
         for entry in &keys {
             if !threads
@@ -199,7 +375,11 @@ fn do_daemon(socket_path: &Path) {
 
         if !threads.contains_key(&(output.clone(), shell)) {
             info!("+ Add thread {}", output.display());
-            let t = sock_try!(PromptThread::new(output.clone(), &|| get_prompt(shell)));
+            let mut t = sock_try!(PromptThread::new(output.clone(), &|| get_prompt(shell)));
+            t.set_timeout(::std::time::Duration::from_millis(thread_timeout));
+            if let Some(prompt) = cache.as_ref().and_then(|c| c.load(&output, shell)) {
+                t.set_cached(prompt);
+            }
             let _ = threads.insert((output.clone(), shell), t);
         }
 
@@ -208,11 +388,41 @@ fn do_daemon(socket_path: &Path) {
         }
 
         let thr = threads
-            .get_mut(&(output, shell))
+            .get_mut(&(output.clone(), shell))
             .expect("Thread not present");
 
+        if wait {
+            if let Some(rx) = thr.subscribe() {
+                info!("Waiting for slow response in the background");
+                let _ = thread::spawn(move || {
+                    let timeout = oneshot_timer(Duration::seconds(30));
+
+                    #[allow(unused_mut)]
+                    {
+                        chan_select! {
+                            rx.recv() -> text => if let Some(t) = text {
+                                let _ = write!(c, "{}", t);
+                                if let Some(cache) = PromptCache::new() {
+                                    let _ = cache.store(&output, shell, &t);
+                                }
+                            },
+                            timeout.recv() => {}
+                        }
+                    }
+                });
+            }
+            continue;
+        }
+
         info!("Getting response from thread");
-        sock_try!(write!(c, "{}", sock_try!(thr.get(&|| get_prompt(shell)))));
+        let prompt = sock_try!(thr.get(&|| get_prompt(shell)));
+        sock_try!(write!(c, "{}", prompt));
+
+        if !thr.is_pending() {
+            if let Some(ref cache) = cache {
+                let _ = cache.store(&output, shell, &prompt);
+            }
+        }
 
         info!("");
 
@@ -259,21 +469,86 @@ fn read_with_timeout(mut stream: UnixStream, dur: Duration) -> Result<String, St
     }
 }
 
-fn do_main(socket_path: &Path, shell: ShellType) {
+/// What to show when the daemon can't answer in time
+#[derive(Clone, Copy)]
+enum Fallback {
+    /// Render the whole prompt locally, however long it takes
+    Full,
+    /// Render the prompt locally, skipping slow plugins
+    Fast,
+    /// The last prompt the daemon sent for this directory
+    Cache,
+    /// Render the prompt locally without any plugins
+    Minimal,
+}
+
+impl FromStr for Fallback {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Fallback, ()> {
+        match s {
+            "full" => Ok(Fallback::Full),
+            "fast" => Ok(Fallback::Fast),
+            "cache" => Ok(Fallback::Cache),
+            "minimal" => Ok(Fallback::Minimal),
+            _ => Err(()),
+        }
+    }
+}
+
+fn print_fallback(fallback: Fallback, path: &Path, shell: ShellType) {
+    if let Fallback::Cache = fallback {
+        if let Some(prompt) = PromptCache::new().and_then(|c| c.load(path, shell)) {
+            println!("{}", prompt);
+            return;
+        }
+    }
+
+    let mut prompt = get_prompt(shell);
+    match fallback {
+        Fallback::Full => prompt.print(),
+        Fallback::Minimal => println!("{}", prompt.convert_to_string_ext(PluginSpeed::Ignored)),
+        _ => prompt.print_fast(),
+    }
+}
+
+fn do_main(socket_path: &Path, settings: &Settings, shell: ShellType) {
+    let fallback = settings.get_or("client.fallback", Fallback::Fast);
+    let offline = settings.get_or("client.offline", Fallback::Full);
+    let timeout: u64 = settings.get_or("client.timeout", 100);
+    let path = env::current_dir().expect("There is no current dir");
+
     let _ = Command::new("megapromptd").arg("start").output();
 
     let mut stream = match UnixStream::connect(socket_path) {
         Err(_) => {
-            println!("Can't connect");
-            get_prompt(shell).print();
+            print_fallback(offline, &path, shell);
             return;
         }
         Ok(stream) => stream,
     };
 
+    write!(&mut stream, "!2 {} {:?}", path.display(), shell)
+        .expect("Unable to print current directory");
+    stream
+        .shutdown(Shutdown::Write)
+        .expect("Cannot shutdown stream");
+
+    match read_with_timeout(stream, Duration::milliseconds(timeout as i64)) {
+        Ok(s) => println!("{}", s),
+        Err(_) => print_fallback(fallback, &path, shell),
+    }
+}
+
+fn do_wait(socket_path: &Path, shell: ShellType) {
+    let mut stream = match UnixStream::connect(socket_path) {
+        Err(_) => return,
+        Ok(stream) => stream,
+    };
+
     write!(
         &mut stream,
-        "!2 {} {:?}",
+        "!wait !2 {} {:?}",
         env::current_dir()
             .expect("There is no current dir")
             .display(),
@@ -283,22 +558,23 @@ fn do_main(socket_path: &Path, shell: ShellType) {
         .shutdown(Shutdown::Write)
         .expect("Cannot shutdown stream");
 
-    match read_with_timeout(stream, Duration::milliseconds(100)) {
-        Ok(s) => println!("{}", s),
-        Err(_) => {
-            println!("Response too slow");
-            get_prompt(shell).print_fast();
-            return;
-        }
+    let mut response = String::new();
+    if stream.read_to_string(&mut response).is_ok() && !response.is_empty() {
+        println!("{}", response);
     }
 }
 
 fn run(mode: RunMode, shell: ShellType) {
-    let socket_path = Path::new("/tmp/megaprompt-socket");
+    let settings = Settings::load();
+    let socket_path = PathBuf::from(
+        settings.get_or("daemon.socket", "/tmp/megaprompt-socket".to_owned()),
+    );
+    let socket_path = socket_path.as_path();
 
     match mode {
-        RunMode::Daemon => do_daemon(socket_path),
-        RunMode::Main => do_main(socket_path, shell),
+        RunMode::Daemon => do_daemon(socket_path, &settings),
+        RunMode::Main => do_main(socket_path, &settings, shell),
+        RunMode::Wait => do_wait(socket_path, shell),
         RunMode::Test => {}
     }
 }
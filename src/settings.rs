@@ -0,0 +1,97 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! User settings for megaprompt.
+//!
+//! Settings are read from `$XDG_CONFIG_HOME/megaprompt/config`, which holds one
+//! `key = value` pair per line. Any key can be overridden from the environment
+//! by upper casing it, replacing `.` with `_` and prefixing it with
+//! `MEGAPROMPT_`, so `client.timeout` becomes `MEGAPROMPT_CLIENT_TIMEOUT`.
+//!
+//! Some git settings can also be set per repository, in a `.megaprompt` file
+//! at the root of its working directory using the same format.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Finds an XDG base directory, falling back to `$HOME/<default>`
+pub fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::home_dir().map(|home| home.join(default)),
+    }
+}
+
+#[derive(Default)]
+pub struct Settings {
+    values: HashMap<String, String>,
+}
+
+impl Settings {
+    pub fn load() -> Settings {
+        match xdg_dir("XDG_CONFIG_HOME", ".config") {
+            Some(dir) => Settings::load_file(&dir.join("megaprompt").join("config")),
+            None => Settings::default(),
+        }
+    }
+
+    /// Reads settings from a file, which may not exist
+    pub fn load_file(path: &Path) -> Settings {
+        let mut settings = Settings::default();
+
+        if let Ok(file) = File::open(path) {
+            for line in BufReader::new(file).lines() {
+                let line = match line {
+                    Ok(l) => l,
+                    Err(_) => break,
+                };
+                let line = line.trim();
+
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                let mut parts = line.splitn(2, '=');
+                if let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+                    let _ = settings
+                        .values
+                        .insert(key.trim().to_owned(), value.trim().to_owned());
+                }
+            }
+        }
+
+        settings
+    }
+
+    /// Looks up a raw setting, preferring the environment over the config file
+    pub fn get(&self, key: &str) -> Option<String> {
+        let var = format!("MEGAPROMPT_{}", key.to_uppercase().replace('.', "_"));
+        match env::var(var) {
+            Ok(value) => Some(value),
+            Err(_) => self.values.get(key).cloned(),
+        }
+    }
+
+    /// Looks up a setting and parses it, using `default` if it is missing or invalid
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        match self.get(key) {
+            Some(value) => match value.trim().parse() {
+                Ok(v) => v,
+                Err(_) => {
+                    warn!("Ignoring invalid value {:?} for {}", value, key);
+                    default
+                }
+            },
+            None => default,
+        }
+    }
+}
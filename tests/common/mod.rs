@@ -0,0 +1,49 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::sync::{Once, ONCE_INIT};
+
+/// Points settings and the global git config at a directory that doesn't
+/// exist and drops `MEGAPROMPT_*` overrides, so the settings of whoever runs
+/// the tests can't change what the plugins render. Call it before creating a
+/// plugin.
+pub fn isolate_settings() {
+    static ISOLATE: Once = ONCE_INIT;
+    ISOLATE.call_once(|| {
+        let empty = env::temp_dir().join("megaprompt-tests-no-config");
+        env::set_var("XDG_CONFIG_HOME", &empty);
+        env::set_var("HOME", &empty);
+        env::set_var("GIT_CONFIG_NOSYSTEM", "1");
+        for (key, _) in env::vars() {
+            if key.starts_with("MEGAPROMPT_") {
+                env::remove_var(key);
+            }
+        }
+    });
+}
+
+/// Strips the `\[...\]` wrapped escapes from a bash prompt, leaving only the
+/// visible text
+pub fn plain(prompt: &str) -> String {
+    let mut result = String::new();
+    let mut rest = prompt;
+    while let Some(start) = rest.find("\\[") {
+        result.push_str(&rest[..start]);
+        rest = match rest[start..].find("\\]") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+    }
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,528 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reads `.due` files in both formats and resolves their dates against a
+//! fixed point in time, then runs `DueDatePlugin` against nested directories.
+
+extern crate megaprompt;
+extern crate prompt_buffer;
+extern crate tempdir;
+extern crate term;
+extern crate time;
+
+mod common;
+
+use megaprompt::due_date::{self, file, manage, urgency, when, DueDatePlugin};
+use megaprompt::due_date::repeat::Repeat;
+use megaprompt::due_date::urgency::Bands;
+use prompt_buffer::{PluginSpeed, PromptBuffer, ShellType};
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Once, ONCE_INIT};
+use tempdir::TempDir;
+use term::color;
+use time::{Timespec, Tm};
+
+use common::{isolate_settings, plain};
+
+/// Wednesday 2017-11-01 12:00:00 UTC. The first call pins the local time
+/// zone, which recurrence rules use, to UTC as well.
+fn now() -> Tm {
+    static UTC: Once = ONCE_INIT;
+    UTC.call_once(|| {
+        env::set_var("TZ", "UTC");
+        time::tzset();
+    });
+    time::at_utc(Timespec::new(1_509_537_600, 0))
+}
+
+fn resolve(s: &str) -> Option<i64> {
+    when::parse(s, &now()).map(|t| t.sec)
+}
+
+#[test]
+fn legacy_dates() {
+    assert_eq!(resolve("Fri Nov 03 17:00:00 2017"), Some(1_509_728_400));
+}
+
+#[test]
+fn iso_dates() {
+    assert_eq!(resolve("2017-11-03"), Some(1_509_667_200));
+    assert_eq!(resolve("2017-11-03T17:00"), Some(1_509_728_400));
+    assert_eq!(resolve("2017-11-03 17:00:00Z"), Some(1_509_728_400));
+    assert_eq!(resolve("2017-11-03T17:00:00+02:00"), Some(1_509_721_200));
+    assert_eq!(resolve("2017-11-03T17:00:00-0130"), Some(1_509_733_800));
+    assert_eq!(resolve("2017-13-03"), None);
+    assert_eq!(resolve("2017-02-31"), None);
+    assert_eq!(resolve("2017-02-29"), None);
+    assert_eq!(resolve("2016-02-29"), Some(1_456_704_000));
+    assert_eq!(resolve("2017-11-03T25:00"), None);
+}
+
+#[test]
+fn relative_dates() {
+    assert_eq!(resolve("today 13:00"), Some(1_509_541_200));
+    assert_eq!(resolve("tomorrow"), Some(1_509_580_800));
+    assert_eq!(resolve("Friday 17:00"), Some(1_509_728_400));
+    assert_eq!(resolve("fri 17:00"), Some(1_509_728_400));
+    assert_eq!(resolve("wednesday 13:00"), Some(1_509_541_200));
+    assert_eq!(resolve("wednesday 09:00"), Some(1_510_131_600));
+    assert_eq!(resolve("next wednesday"), Some(1_510_099_200));
+    assert_eq!(resolve("in 3 days"), Some(1_509_796_800));
+    assert_eq!(resolve("in 1 hour"), Some(1_509_541_200));
+    assert_eq!(resolve("someday"), None);
+    assert_eq!(resolve("next tomorrow"), None);
+    // Far-off dates are refused rather than overflowing
+    assert_eq!(resolve("in 200000000000 days"), None);
+    assert_eq!(resolve("in 9223372036854775807 minutes"), None);
+    assert_eq!(resolve("in -9223372036854775808 weeks"), None);
+}
+
+#[test]
+fn legacy_file() {
+    let deadlines = file::parse("Fri Nov 03 17:00:00 2017\nRelease\nships in\nshipped\n").deadlines;
+    assert_eq!(
+        deadlines,
+        vec![
+            file::Deadline {
+                name: None,
+                due: "Fri Nov 03 17:00:00 2017".to_owned(),
+                title: "Release".to_owned(),
+                future: "ships in".to_owned(),
+                past: "shipped".to_owned(),
+                bands: None,
+                repeat: None,
+            },
+        ]
+    );
+
+    let deadlines = file::parse("Fri Nov 03 17:00:00 2017\n").deadlines;
+    assert_eq!(deadlines[0].title, file::DEFAULT_TITLE);
+    assert_eq!(deadlines[0].future, file::DEFAULT_FUTURE);
+    assert_eq!(deadlines[0].past, file::DEFAULT_PAST);
+}
+
+#[test]
+fn structured_file() {
+    let deadlines = file::parse(
+        "# Deadlines for this project\n\
+         due = 2017-12-01\n\
+         \n\
+         [release]\n\
+         due = 2017-11-03T17:00:00+02:00\n\
+         title = Release 1.0\n\
+         \n\
+         [demo]\n\
+         due = next friday 15:00\n\
+         future = starts in\n\
+         past = started\n\
+         \n\
+         [someday]\n\
+         title = Never\n",
+    ).deadlines;
+
+    assert_eq!(deadlines.len(), 4);
+    assert_eq!(deadlines[0].name, None);
+    assert_eq!(deadlines[0].title, "Project");
+    assert_eq!(deadlines[0].due, "2017-12-01");
+    assert_eq!(deadlines[1].name, Some("release".to_owned()));
+    assert_eq!(deadlines[1].title, "Release 1.0");
+    assert_eq!(deadlines[2].title, "demo");
+    assert_eq!(deadlines[2].due, "next friday 15:00");
+    assert_eq!(deadlines[2].future, "starts in");
+    assert_eq!(deadlines[2].past, "started");
+    assert!(deadlines[2].is_dated());
+    // Kept so rewriting the file doesn't lose it, and only warned about
+    assert_eq!(deadlines[3].title, "Never");
+    assert!(!deadlines[3].is_dated());
+}
+
+#[test]
+fn file_problems() {
+    let due_file = file::parse("[demo]\ndue = 2017-11-03\ntitel = Demo\njust some text\n");
+    assert_eq!(due_file.deadlines.len(), 1);
+    assert_eq!(
+        due_file.problems,
+        vec![
+            "line 3: unknown key \"titel\"".to_owned(),
+            "line 4: expected \"key = value\" or \"[section]\", not \"just some text\"".to_owned(),
+        ]
+    );
+}
+
+#[test]
+fn default_bands() {
+    let bands = Bands::default();
+    let hour = 60 * 60;
+
+    assert_eq!(bands.for_remaining(-1).color, color::RED);
+    assert!(bands.for_remaining(-1).bold);
+    assert!(bands.for_remaining(30 * 60).blink);
+    assert_eq!(bands.for_remaining(24 * hour).color, color::YELLOW);
+    assert_eq!(bands.for_remaining(72 * hour).color, color::CYAN);
+    assert_eq!(bands.for_remaining(30 * 24 * hour).color, color::GREEN);
+}
+
+#[test]
+fn custom_bands() {
+    let bands: Bands = "2d yellow ⏳, 30m red bold blink 🔥"
+        .parse()
+        .expect("Valid bands");
+    let urgent = bands.for_remaining(10 * 60);
+    assert_eq!(urgent.color, color::RED);
+    assert!(urgent.bold && urgent.blink);
+    assert_eq!(urgent.icon, Some("🔥".to_owned()));
+    assert_eq!(bands.for_remaining(60 * 60).icon, Some("⏳".to_owned()));
+    // Without a catch-all the largest threshold is used for anything later
+    assert_eq!(bands.for_remaining(100 * 24 * 60 * 60).color, color::YELLOW);
+    assert!(!bands.for_remaining(-1).blink);
+
+    assert!("1h purple".parse::<Bands>().is_err());
+    assert!("soon red".parse::<Bands>().is_err());
+    assert!("1h".parse::<Bands>().is_err());
+    assert!("1h red a b".parse::<Bands>().is_err());
+    assert!("5é red".parse::<Bands>().is_err());
+    assert!("é red".parse::<Bands>().is_err());
+    assert!("9999999999999999w red".parse::<Bands>().is_err());
+    assert_eq!(
+        urgency::DEFAULT_BANDS.parse::<Bands>().ok(),
+        Some(Bands::default())
+    );
+}
+
+#[test]
+fn bands_in_file() {
+    let due_file = file::parse(
+        "bands = 1d red\n\
+         [release]\n\
+         due = 2017-11-03\n\
+         [demo]\n\
+         due = 2017-11-04\n\
+         bands = 1h yellow\n",
+    );
+
+    assert_eq!(due_file.bands, Some("1d red".to_owned()));
+    assert_eq!(due_file.deadlines.len(), 2);
+    assert_eq!(due_file.deadlines[0].bands, None);
+    assert_eq!(due_file.deadlines[1].bands, Some("1h yellow".to_owned()));
+}
+
+fn write(path: &Path, contents: &[u8]) {
+    fs::create_dir_all(path.parent().expect("Path has a parent")).expect("Unable to create dir");
+    File::create(path)
+        .and_then(|mut f| f.write_all(contents))
+        .expect("Unable to write file");
+}
+
+fn render(path: &Path, nearest: usize) -> String {
+    isolate_settings();
+    let mut plugin = DueDatePlugin::new();
+    plugin.set_nearest(nearest);
+    let mut buffer = PromptBuffer::new(ShellType::Bash);
+    buffer.add_plugin(plugin);
+    buffer.set_path(path.to_path_buf());
+    plain(&buffer.convert_to_string_ext(PluginSpeed::Slow))
+}
+
+#[test]
+fn nested_files() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let root = dir.path();
+    let child = root.join("child");
+    write(&root.join(".due"), b"[later]\ndue = 2099-06-01\n[overdue]\ndue = 2017-11-01\n");
+    write(&child.join(".due"), b"[soon]\ndue = 2098-06-01\n[broken]\ndue = someday\n");
+
+    let prompt = render(&child, 0);
+    let lines = prompt
+        .lines()
+        .filter(|l| l.contains(&*root.to_string_lossy()))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4, "{}", prompt);
+
+    // Most urgent first, each labeled with where it came from
+    assert!(lines[0].contains(&format!("┤{}├", root.display())), "{}", prompt);
+    assert!(lines[0].contains("overdue was due"), "{}", prompt);
+    assert!(lines[1].contains(&format!("┤{}├", child.display())), "{}", prompt);
+    assert!(lines[1].contains("soon is due in"), "{}", prompt);
+    assert!(lines[2].contains("later is due in"), "{}", prompt);
+    assert!(lines[3].contains("unable to parse due date \"someday\""), "{}", prompt);
+
+    let prompt = render(&child, 1);
+    assert!(prompt.contains("soon is due in"), "{}", prompt);
+    assert!(!prompt.contains("later"), "{}", prompt);
+}
+
+/// Sets the mtime of `path` to `secs` since the Unix epoch
+fn set_mtime(path: &Path, secs: i64) {
+    let status = Command::new("touch")
+        .arg("-d")
+        .arg(format!("@{}", secs))
+        .arg(path)
+        .status()
+        .expect("Unable to run touch");
+    assert!(status.success());
+}
+
+#[test]
+fn relative_dates_count_from_mtime() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let due = dir.path().join(".due");
+    write(&due, b"[demo]\ndue = in 3 days\n");
+    set_mtime(&due, time::get_time().sec - 2 * 24 * 60 * 60 + 600);
+
+    let prompt = render(dir.path(), 0);
+    assert!(prompt.contains("demo is due in: 1 day├"), "{}", prompt);
+    assert!(!prompt.contains("days"), "{}", prompt);
+}
+
+#[test]
+fn malformed_files() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let child = dir.path().join("child");
+    // Not quite the original format, so nothing in it is understood
+    write(&dir.path().join(".due"), b"Fri Nov 3 2017\nRelease\n");
+    write(
+        &child.join(".due"),
+        b"[demo]\ndue = 2099-01-01\ntitel = Demo\n[later]\ntitle = Nothing yet\n",
+    );
+
+    let prompt = render(&child, 0);
+    assert!(prompt.contains("demo is due in"), "{}", prompt);
+    assert!(prompt.contains(".due line 3: unknown key \"titel\""), "{}", prompt);
+    assert!(prompt.contains("Nothing yet: no due date"), "{}", prompt);
+    assert!(
+        prompt.contains(".due line 1: expected \"key = value\" or \"[section]\", not \"Fri Nov 3 2017\""),
+        "{}",
+        prompt
+    );
+    assert!(prompt.contains(".due line 2: expected"), "{}", prompt);
+}
+
+#[test]
+fn unreadable_file() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    write(&dir.path().join(".due"), b"due = tomorrow\xff\xfe\n");
+
+    let prompt = render(dir.path(), 0);
+    assert!(prompt.contains("unable to read .due"), "{}", prompt);
+}
+
+fn read(path: &Path) -> String {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .expect("Unable to read file");
+    contents
+}
+
+#[test]
+fn set_deadlines() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let due = dir.path().join(".due");
+
+    let at = manage::set(dir.path(), None, "2017-11-03 17:00", Some("Release"), None, &now());
+    assert_eq!(at.map(|t| t.sec), Ok(1_509_728_400));
+    // Relative dates are pinned so they don't move
+    manage::set(dir.path(), Some("demo"), "tomorrow", None, None, &now()).expect("Valid date");
+    assert_eq!(
+        read(&due),
+        "due = 2017-11-03T17:00:00+00:00\n\
+         title = Release\n\
+         \n\
+         [demo]\n\
+         due = 2017-11-02T00:00:00+00:00\n"
+    );
+
+    manage::set(dir.path(), Some("demo"), "in 3 days", None, None, &now()).expect("Valid date");
+    let deadlines = file::parse(&read(&due)).deadlines;
+    assert_eq!(deadlines.len(), 2);
+    assert_eq!(deadlines[1].due, "2017-11-04T12:00:00+00:00");
+
+    assert!(manage::set(dir.path(), None, "someday", None, None, &now()).is_err());
+    assert!(manage::set(dir.path(), None, "in 200000000000 days", None, None, &now()).is_err());
+    assert!(manage::set(dir.path(), Some("a]b"), "tomorrow", None, None, &now()).is_err());
+    assert_eq!(file::parse(&read(&due)).deadlines, deadlines);
+    // Written through a temporary file that is renamed into place
+    let files = fs::read_dir(dir.path()).expect("Unable to list dir").count();
+    assert_eq!(files, 1);
+}
+
+#[test]
+fn set_keeps_file_bands() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let due = dir.path().join(".due");
+    write(&due, b"bands = 1d red\n[release]\ndue = 2017-11-03\n");
+
+    manage::set(dir.path(), Some("demo"), "2017-11-04", None, None, &now()).expect("Valid date");
+    assert_eq!(
+        read(&due),
+        "bands = 1d red\n\
+         \n\
+         [release]\n\
+         due = 2017-11-03\n\
+         \n\
+         [demo]\n\
+         due = 2017-11-04T00:00:00+00:00\n"
+    );
+
+    let due_file = file::parse(&read(&due));
+    assert_eq!(due_file.bands, Some("1d red".to_owned()));
+    assert_eq!(due_file.deadlines[1].bands, None);
+
+    assert_eq!(manage::clear(dir.path(), Some("release"), &now()), Ok(1));
+    assert!(read(&due).starts_with("bands = 1d red\n"), "{}", read(&due));
+}
+
+#[test]
+fn set_pins_relative_dates_in_file() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let due = dir.path().join(".due");
+    write(&due, b"[review]\ndue = in 3 days\n");
+    set_mtime(&due, 1_509_000_000);
+
+    manage::set(dir.path(), Some("demo"), "2017-11-03", None, None, &now()).expect("Valid date");
+    let deadlines = file::parse(&read(&due)).deadlines;
+    assert_eq!(deadlines.len(), 2);
+    assert_eq!(deadlines[0].due, "2017-10-29T06:40:00+00:00");
+}
+
+#[test]
+fn set_converts_legacy_files() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let due = dir.path().join(".due");
+    write(&due, b"Fri Nov 03 17:00:00 2017\nRelease\nships in\nshipped\n");
+
+    manage::set(dir.path(), Some("demo"), "friday 15:00", None, None, &now()).expect("Valid date");
+    let deadlines = file::parse(&read(&due)).deadlines;
+    assert_eq!(deadlines.len(), 2);
+    assert_eq!(deadlines[0].title, "Release");
+    assert_eq!(deadlines[0].future, "ships in");
+    assert_eq!(deadlines[0].past, "shipped");
+    assert_eq!(deadlines[1].due, "2017-11-03T15:00:00+00:00");
+}
+
+#[test]
+fn clear_deadlines() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let due = dir.path().join(".due");
+    write(&due, b"[release]\ndue = 2017-11-03\n[demo]\ndue = 2017-11-04\n");
+
+    assert_eq!(manage::clear(dir.path(), Some("other"), &now()), Ok(0));
+    assert_eq!(manage::clear(dir.path(), Some("demo"), &now()), Ok(1));
+    assert_eq!(read(&due), "[release]\ndue = 2017-11-03\n");
+    assert_eq!(manage::clear(dir.path(), None, &now()), Ok(1));
+    assert!(!due.exists());
+    assert_eq!(manage::clear(dir.path(), None, &now()), Ok(0));
+}
+
+#[test]
+fn list_deadlines() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let child = dir.path().join("child");
+    write(&dir.path().join(".due"), b"due = 2017-12-01\n");
+    write(&child.join(".due"), b"[demo]\ndue = 2017-11-04\n");
+
+    let listed = manage::list(&child);
+    let listed = listed
+        .iter()
+        .filter(|&&(ref file, _)| file.starts_with(dir.path()))
+        .collect::<Vec<_>>();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0].0, child.join(".due"));
+    assert_eq!(
+        listed[0].1.as_ref().map(|f| f.deadlines[0].name.clone()),
+        Ok(Some("demo".to_owned()))
+    );
+    assert_eq!(listed[1].0, dir.path().join(".due"));
+}
+
+fn next(repeat: &str, due: Option<&str>) -> Result<i64, String> {
+    let due = due.map(|d| when::parse(d, &now()).expect("Valid date"));
+    repeat
+        .parse::<Repeat>()
+        .and_then(|r| r.next(due, &now()))
+        .map(|t| t.sec)
+}
+
+#[test]
+fn fixed_repeats() {
+    assert_eq!(next("weekly", Some("2017-10-06T15:00Z")), Ok(1_509_721_200));
+    assert_eq!(next("biweekly", Some("2017-10-13T15:00Z")), Ok(1_510_326_000));
+    assert_eq!(next("every 2 weeks", Some("2017-10-13T15:00Z")), Ok(1_510_326_000));
+    assert_eq!(next("daily", Some("2017-10-01T13:00Z")), Ok(1_509_541_200));
+    // Recurrences start at the due date
+    assert_eq!(next("weekly", Some("2017-12-01T00:00Z")), Ok(1_512_086_400));
+    assert!(next("weekly", None).is_err());
+    assert!(next("every 9999999999999999 weeks", Some("2017-10-01")).is_err());
+    assert!(next("every 1000000000000 weeks", Some("2017-10-01")).is_err());
+}
+
+#[test]
+fn monthly_repeats() {
+    // Short months use their last day
+    assert_eq!(next("monthly", Some("2017-01-31T09:00Z")), Ok(1_512_032_400));
+    assert_eq!(next("monthly on 1", Some("2017-01-01T13:00Z")), Ok(1_509_541_200));
+    assert_eq!(next("monthly on 1", Some("2017-01-01T11:00Z")), Ok(1_512_126_000));
+    assert!(next("monthly on 32", Some("2017-01-01")).is_err());
+}
+
+#[test]
+fn cron_repeats() {
+    assert_eq!(next("cron 0 15 * * 5", None), Ok(1_509_721_200));
+    assert_eq!(next("cron */30 * * * *", None), Ok(1_509_537_600));
+    assert_eq!(next("cron 0 9 29 2 *", None), Ok(1_582_966_800));
+    // A restricted day of month and weekday match either, as in cron
+    assert_eq!(next("cron 0 0 13 * 5", None), Ok(1_509_667_200));
+    // but a field starting with `*` doesn't restrict, even with a step
+    assert_eq!(next("cron 0 0 */2 * 1", None), Ok(1_509_926_400));
+    assert_eq!(next("cron 0 0 1 * */2", None), Ok(1_512_086_400));
+    assert_eq!(next("cron 0 15 * * 5", Some("2017-11-04")), Ok(1_510_326_000));
+
+    assert!(next("cron 60 * * * *", None).is_err());
+    assert!(next("cron 0 0 * *", None).is_err());
+    assert!(next("cron 0 0 31 2 *", None).is_err());
+    assert!(next("fortnightly", None).is_err());
+}
+
+#[test]
+fn set_keeps_repeat() {
+    let dir = TempDir::new("megaprompt-due").expect("Unable to create temp dir");
+    let due = dir.path().join(".due");
+
+    let at = manage::set(dir.path(), None, "2017-10-25", None, Some(Some("weekly")), &now());
+    assert_eq!(at.map(|t| t.sec), Ok(1_510_099_200));
+    let at = manage::set(dir.path(), None, "2017-10-18", Some("Demo"), None, &now());
+    assert_eq!(at.map(|t| t.sec), Ok(1_510_099_200));
+    assert_eq!(file::parse(&read(&due)).deadlines[0].repeat, Some("weekly".to_owned()));
+
+    let at = manage::set(dir.path(), None, "2017-10-18", None, Some(None), &now());
+    assert_eq!(at.map(|t| t.sec), Ok(1_508_284_800));
+    assert_eq!(file::parse(&read(&due)).deadlines[0].repeat, None);
+    let invalid = manage::set(dir.path(), None, "2017-10-18", None, Some(Some("often")), &now());
+    assert!(invalid.is_err());
+    assert_eq!(file::parse(&read(&due)).deadlines[0].repeat, None);
+}
+
+#[test]
+fn repeating_file() {
+    let due_file = file::parse(
+        "[demo]\n\
+         repeat = cron 0 15 * * 5\n\
+         [sprint]\n\
+         due = 2017-10-13T15:00Z\n\
+         repeat = biweekly\n",
+    );
+    let deadlines = &due_file.deadlines;
+    assert_eq!(deadlines.len(), 2);
+    assert_eq!(due_date::resolve(&deadlines[0], &now(), &now()).map(|t| t.sec), Ok(1_509_721_200));
+    assert_eq!(due_date::resolve(&deadlines[1], &now(), &now()).map(|t| t.sec), Ok(1_510_326_000));
+    assert_eq!(file::parse(&file::render(&due_file)), due_file);
+}
@@ -0,0 +1,82 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parses dates and runs recurrence rules in a time zone with daylight saving
+//! time. The time zone is set for the whole process, so this needs a test
+//! binary of its own.
+
+extern crate megaprompt;
+extern crate time;
+
+use megaprompt::due_date::repeat::Repeat;
+use megaprompt::due_date::{manage, when};
+use std::env;
+use std::sync::{Once, ONCE_INIT};
+use time::{Timespec, Tm};
+
+/// A point in time in New York, which left daylight saving time on 2017-11-05
+fn new_york(at: i64) -> Tm {
+    static NEW_YORK: Once = ONCE_INIT;
+    NEW_YORK.call_once(|| {
+        env::set_var("TZ", "America/New_York");
+        time::tzset();
+    });
+    time::at(Timespec::new(at, 0))
+}
+
+fn parse(s: &str, now: i64) -> Option<i64> {
+    when::parse(s, &new_york(now)).map(|t| t.sec)
+}
+
+fn next(repeat: &str, due: Option<i64>, now: i64) -> Result<i64, String> {
+    let now = new_york(now);
+    repeat
+        .parse::<Repeat>()
+        .and_then(|r| r.next(due.map(|d| Timespec::new(d, 0)), &now))
+        .map(|t| t.sec)
+}
+
+#[test]
+fn dates_keep_wall_clock_time() {
+    // Wednesday 2017-11-01 12:00 EDT to Friday 2017-11-10 15:00 EST
+    assert_eq!(parse("2017-11-10 15:00", 1_509_552_000), Some(1_510_344_000));
+    // Friday 2017-11-03 12:00 EDT to Friday 2017-11-10 17:00 EST
+    assert_eq!(parse("next friday 17:00", 1_509_724_800), Some(1_510_351_200));
+    // An explicit offset is taken as is
+    assert_eq!(parse("2017-11-10T15:00:00-04:00", 1_509_552_000), Some(1_510_340_400));
+}
+
+#[test]
+fn dates_written_with_their_own_offset() {
+    new_york(0);
+    assert_eq!(manage::format_iso(Timespec::new(1_509_735_600, 0)), "2017-11-03T15:00:00-04:00");
+    assert_eq!(manage::format_iso(Timespec::new(1_510_344_000, 0)), "2017-11-10T15:00:00-05:00");
+}
+
+#[test]
+fn set_then_repeat_keeps_wall_clock_time() {
+    // Parsed before the change, repeating weekly from after it
+    let due = parse("2017-11-03 15:00", 1_509_552_000);
+    assert_eq!(due, Some(1_509_735_600));
+    assert_eq!(next("weekly", due, 1_510_160_400), Ok(1_510_344_000));
+}
+
+#[test]
+fn repeats_keep_wall_clock_time() {
+    // Wednesday 2017-11-08 12:00 EST
+    let now = 1_510_160_400;
+
+    // Friday 2017-10-27 15:00 EDT to Friday 2017-11-10 15:00 EST
+    assert_eq!(next("weekly", Some(1_509_130_800), now), Ok(1_510_344_000));
+    // 2017-10-15 09:00 EDT to 2017-11-15 09:00 EST
+    assert_eq!(next("monthly", Some(1_508_072_400), now), Ok(1_510_754_400));
+    // 15:00 EST on the first Friday after now
+    assert_eq!(next("cron 0 15 * * 5", None, now), Ok(1_510_344_000));
+    // 15:00 EDT before the change, Wednesday 2017-11-01 12:00 EDT
+    assert_eq!(next("cron 0 15 * * 5", None, 1_509_552_000), Ok(1_509_735_600));
+}
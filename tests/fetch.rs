@@ -0,0 +1,126 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fetches between scratch repositories over file remotes.
+
+extern crate git2;
+extern crate megaprompt;
+extern crate tempdir;
+
+use git2::{Oid, Repository};
+use megaprompt::fetch::{fetch_repo, AutoFetch};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use tempdir::TempDir;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+fn git(dir: &Path, args: &[&str]) {
+    let output = Command::new("git")
+        .args(&["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("Unable to run git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn commit(dir: &Path, message: &str) -> Oid {
+    git(dir, &["commit", "-q", "--allow-empty", "-m", message]);
+    Repository::open(dir)
+        .and_then(|r| r.refname_to_id("HEAD"))
+        .expect("Unable to read HEAD")
+}
+
+fn remote_master(dir: &Path) -> Oid {
+    Repository::open(dir)
+        .and_then(|r| r.refname_to_id("refs/remotes/origin/master"))
+        .expect("Unable to read origin/master")
+}
+
+/// An origin with one commit, and a clone of it
+fn origin_and_clone() -> (TempDir, TempDir) {
+    let origin = TempDir::new("megaprompt-origin").expect("Unable to create temp dir");
+    git(origin.path(), &["init", "-q"]);
+    git(origin.path(), &["checkout", "-q", "-b", "master"]);
+    let _ = commit(origin.path(), "Initial commit");
+
+    let clone = TempDir::new("megaprompt-clone").expect("Unable to create temp dir");
+    git(
+        clone.path(),
+        &[
+            "clone",
+            "-q",
+            &format!("{}", origin.path().display()),
+            ".",
+        ],
+    );
+    (origin, clone)
+}
+
+#[test]
+fn fetches_tracked_remote() {
+    let (origin, clone) = origin_and_clone();
+    let new = commit(origin.path(), "Second commit");
+    assert!(remote_master(clone.path()) != new);
+
+    assert_eq!(fetch_repo(clone.path(), TIMEOUT).ok(), Some(true));
+    assert_eq!(remote_master(clone.path()), new);
+    assert!(clone.path().join(".git").join("FETCH_HEAD").exists());
+}
+
+#[test]
+fn nothing_to_fetch_without_remote() {
+    let dir = TempDir::new("megaprompt-local").expect("Unable to create temp dir");
+    git(dir.path(), &["init", "-q"]);
+    let _ = commit(dir.path(), "Initial commit");
+
+    assert_eq!(fetch_repo(dir.path(), TIMEOUT).ok(), Some(false));
+}
+
+#[test]
+fn missing_remote_fails() {
+    let (origin, clone) = origin_and_clone();
+    fs::remove_dir_all(origin.path()).expect("Unable to remove origin");
+
+    assert!(fetch_repo(clone.path(), TIMEOUT).is_err());
+}
+
+#[test]
+fn slow_fetch_aborted() {
+    let (origin, clone) = origin_and_clone();
+    let new = commit(origin.path(), "Second commit");
+
+    assert!(fetch_repo(clone.path(), Duration::from_secs(0)).is_err());
+    assert!(remote_master(clone.path()) != new);
+}
+
+#[test]
+fn auto_fetch_watched_repo() {
+    let (origin, clone) = origin_and_clone();
+    let new = commit(origin.path(), "Second commit");
+
+    let fetch = AutoFetch::new(Duration::from_secs(60), 1, TIMEOUT);
+    fetch.watch(&clone.path().join(".git"));
+
+    for _ in 0..100 {
+        if remote_master(clone.path()) == new {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    panic!("{} was never fetched", clone.path().display());
+}
@@ -0,0 +1,887 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Runs `GitPlugin` against scratch repositories. Any panic inside the plugin
+//! fails the test, since it would take down a daemon thread.
+
+extern crate git2;
+extern crate megaprompt;
+extern crate prompt_buffer;
+extern crate tempdir;
+
+mod common;
+
+use git2::{IndexAddOption, Oid, Repository, Signature};
+use megaprompt::git::GitPlugin;
+use prompt_buffer::{PluginSpeed, PromptBuffer, ShellType};
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tempdir::TempDir;
+
+use common::{isolate_settings, plain};
+
+struct TestRepo {
+    dir: TempDir,
+    repo: Repository,
+}
+
+impl TestRepo {
+    fn new() -> TestRepo {
+        let dir = TempDir::new("megaprompt-git").expect("Unable to create temp dir");
+        let repo = Repository::init(dir.path()).expect("Unable to init repo");
+        {
+            let mut config = repo.config().expect("Unable to open config");
+            config
+                .set_str("user.name", "Test")
+                .expect("Unable to set name");
+            config
+                .set_str("user.email", "test@example.com")
+                .expect("Unable to set email");
+        }
+        TestRepo {
+            dir: dir,
+            repo: repo,
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.path().to_path_buf()
+    }
+
+    fn write(&self, name: &str, contents: &str) {
+        let path = self.dir.path().join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("Unable to create dir");
+        }
+        let mut f = File::create(path).expect("Unable to create file");
+        f.write_all(contents.as_bytes())
+            .expect("Unable to write file");
+    }
+
+    fn stage_all(&self) {
+        let mut index = self.repo.index().expect("Unable to open index");
+        index
+            .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+            .expect("Unable to add files");
+        index
+            .update_all(["*"].iter(), None)
+            .expect("Unable to update files");
+        index.write().expect("Unable to write index");
+    }
+
+    fn commit_all(&self, message: &str) -> Oid {
+        self.stage_all();
+        let mut index = self.repo.index().expect("Unable to open index");
+        let tree = self.repo
+            .find_tree(index.write_tree().expect("Unable to write tree"))
+            .expect("Unable to find tree");
+        let sig = Signature::now("Test", "test@example.com").expect("Unable to create signature");
+        let parent = self.repo
+            .head()
+            .ok()
+            .and_then(|h| h.target())
+            .map(|id| self.repo.find_commit(id).expect("Unable to find parent"));
+        let parents = parent.iter().collect::<Vec<_>>();
+        self.repo
+            .commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .expect("Unable to commit")
+    }
+
+    fn git(&self, args: &[&str]) {
+        git(self.dir.path(), args);
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let output = Command::new("git")
+        .args(&["-c", "protocol.file.allow=always"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("Unable to run git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// A plugin that only sees the defaults, not the settings of whoever runs the tests
+fn plugin() -> GitPlugin {
+    isolate_settings();
+    GitPlugin::new()
+}
+
+fn render_at(path: &Path, speed: PluginSpeed) -> String {
+    let mut buffer = PromptBuffer::new(ShellType::Bash);
+    buffer.add_plugin(plugin());
+    buffer.set_path(path.to_path_buf());
+    plain(&buffer.convert_to_string_ext(speed))
+}
+
+fn render_with_diff_stat(path: &Path, budget: Duration) -> String {
+    let mut plugin = plugin();
+    plugin.set_diff_stat(Some(budget));
+    let mut buffer = PromptBuffer::new(ShellType::Bash);
+    buffer.add_plugin(plugin);
+    buffer.set_path(path.to_path_buf());
+    plain(&buffer.convert_to_string_ext(PluginSpeed::Slow))
+}
+
+fn render(path: &Path) -> String {
+    render_at(path, PluginSpeed::Slow)
+}
+
+fn assert_contains(prompt: &str, expected: &str) {
+    assert!(
+        prompt.contains(expected),
+        "{:?} not found in prompt:\n{}",
+        expected,
+        prompt
+    );
+}
+
+#[test]
+fn not_a_repository() {
+    let dir = TempDir::new("megaprompt-git").expect("Unable to create temp dir");
+    let prompt = render(dir.path());
+    assert_eq!(prompt.lines().count(), 2, "{}", prompt);
+}
+
+#[test]
+fn clean_branch() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤master├");
+    assert!(!prompt.contains("Git Status"), "{}", prompt);
+}
+
+#[test]
+fn fast_speed_skips_status() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+    repo.write("b.txt", "b\n");
+
+    let prompt = render_at(&repo.path(), PluginSpeed::Fast);
+    assert!(!prompt.contains("Git Status"), "{}", prompt);
+    assert_contains(&prompt, "┤master├");
+}
+
+#[test]
+fn unborn_branch() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "Git Status");
+    assert_contains(&prompt, "?? a.txt");
+    assert_contains(&prompt, "┤master├─┤New Repository├");
+}
+
+#[test]
+fn detached_head() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    let first = repo.commit_all("First");
+    repo.write("a.txt", "b\n");
+    repo.commit_all("Second");
+    repo.repo
+        .set_head_detached(first)
+        .expect("Unable to detach head");
+
+    let prompt = render(&repo.path());
+    let short = format!("{}", first);
+    assert_contains(&prompt, &format!("┤detached {}├", &short[..7]));
+}
+
+#[test]
+fn detached_at_tag() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    let first = repo.commit_all("First");
+    repo.git(&["tag", "v1.0"]);
+    repo.write("a.txt", "b\n");
+    repo.commit_all("Second");
+    repo.repo
+        .set_head_detached(first)
+        .expect("Unable to detach head");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤detached at v1.0├");
+}
+
+#[test]
+fn tags_at_head() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("First");
+    repo.git(&["tag", "v1.0"]);
+    repo.git(&["tag", "-a", "-m", "Release", "release"]);
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤tag release, v1.0├");
+
+    // Looking up tags walks all of them, which fast prompts skip
+    let prompt = render_at(&repo.path(), PluginSpeed::Fast);
+    assert!(!prompt.contains("tag "), "{}", prompt);
+
+    repo.write("a.txt", "b\n");
+    repo.commit_all("Second");
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains("tag "), "{}", prompt);
+}
+
+#[test]
+fn local_only_branch() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤master├─┤local only├");
+}
+
+fn clone_of(origin: &TestRepo) -> (TempDir, PathBuf) {
+    let clone_dir = TempDir::new("megaprompt-git").expect("Unable to create temp dir");
+    let clone_path = clone_dir.path().join("clone");
+    Repository::clone(&format!("{}", origin.path().display()), &clone_path)
+        .expect("Unable to clone");
+    git(&clone_path, &["config", "user.name", "Test"]);
+    git(&clone_path, &["config", "user.email", "test@example.com"]);
+    (clone_dir, clone_path)
+}
+
+#[test]
+fn no_upstream_configured() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+    let (_dir, clone) = clone_of(&origin);
+    git(&clone, &["checkout", "-b", "feature"]);
+
+    let prompt = render(&clone);
+    assert_contains(&prompt, "┤feature├─┤no upstream, push -u origin feature├");
+}
+
+#[test]
+fn upstream_gone() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+    origin.git(&["branch", "feature"]);
+    let (_dir, clone) = clone_of(&origin);
+    git(&clone, &["checkout", "feature"]);
+    origin.git(&["branch", "-D", "feature"]);
+    git(&clone, &["fetch", "--prune"]);
+
+    let prompt = render(&clone);
+    assert_contains(&prompt, "┤feature -> origin/feature (gone)├");
+}
+
+#[test]
+fn staged_and_modified_files() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.write("b.txt", "b\n");
+    repo.commit_all("Initial commit");
+    repo.write("a.txt", "changed\n");
+    repo.write("new.txt", "new\n");
+    repo.stage_all();
+    repo.write("b.txt", "changed\n");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "M  a.txt");
+    assert_contains(&prompt, "A  new.txt");
+    assert_contains(&prompt, " M b.txt");
+}
+
+#[test]
+fn renamed_file() {
+    let repo = TestRepo::new();
+    repo.write("old.txt", "some content that is long enough to be a rename\n");
+    repo.commit_all("Initial commit");
+    fs::rename(repo.path().join("old.txt"), repo.path().join("new.txt"))
+        .expect("Unable to rename");
+    repo.stage_all();
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "R  old.txt -> new.txt");
+}
+
+#[test]
+fn deleted_file() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.write("b.txt", "b\n");
+    repo.commit_all("Initial commit");
+    fs::remove_file(repo.path().join("a.txt")).expect("Unable to delete");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, " D a.txt");
+
+    repo.stage_all();
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "D  a.txt");
+}
+
+#[test]
+fn type_change() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.write("link", "not a link yet\n");
+    repo.commit_all("Initial commit");
+    fs::remove_file(repo.path().join("link")).expect("Unable to delete");
+    symlink("a.txt", repo.path().join("link")).expect("Unable to symlink");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, " T link");
+}
+
+#[test]
+fn paths_relative_to_subdirectory() {
+    let repo = TestRepo::new();
+    repo.write("top.txt", "a\n");
+    repo.write("sub/inner.txt", "b\n");
+    repo.commit_all("Initial commit");
+    repo.write("top.txt", "changed\n");
+    repo.write("sub/inner.txt", "changed\n");
+
+    let prompt = render(&repo.path().join("sub"));
+    assert_contains(&prompt, " M ../top.txt");
+    assert_contains(&prompt, " M inner.txt");
+}
+
+#[test]
+fn outgoing_commits() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+
+    let (_dir, clone_path) = clone_of(&origin);
+    git(&clone_path, &["commit", "--allow-empty", "-m", "Not pushed yet"]);
+
+    let prompt = render(&clone_path);
+    assert_contains(&prompt, "Git Outgoing");
+    assert_contains(&prompt, "Not pushed yet");
+    assert_contains(&prompt, "master -> origin/master");
+}
+
+fn render_with_outgoing(path: &Path, limit: usize, author: bool, date: bool) -> String {
+    let mut plugin = plugin();
+    plugin.set_outgoing(limit, author, date);
+    let mut buffer = PromptBuffer::new(ShellType::Bash);
+    buffer.add_plugin(plugin);
+    buffer.set_path(path.to_path_buf());
+    plain(&buffer.convert_to_string_ext(PluginSpeed::Slow))
+}
+
+#[test]
+fn outgoing_limit() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+
+    let (_dir, clone_path) = clone_of(&origin);
+    for i in 0..5 {
+        git(
+            &clone_path,
+            &["commit", "--allow-empty", "-m", &format!("Commit {}", i)],
+        );
+    }
+
+    let prompt = render_with_outgoing(&clone_path, 3, false, false);
+    assert_contains(&prompt, "Commit 4");
+    assert_contains(&prompt, "Commit 2");
+    assert!(!prompt.contains("Commit 1"), "{}", prompt);
+    assert_contains(&prompt, "… and 2 more");
+
+    let prompt = render_with_outgoing(&clone_path, 0, false, false);
+    assert_contains(&prompt, "Commit 0");
+    assert!(!prompt.contains("more"), "{}", prompt);
+}
+
+#[test]
+fn outgoing_without_message() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+
+    let (_dir, clone_path) = clone_of(&origin);
+    git(&clone_path, &["commit", "--allow-empty", "-m", "First"]);
+    git(
+        &clone_path,
+        &["commit", "--allow-empty", "--allow-empty-message", "-m", ""],
+    );
+    git(&clone_path, &["commit", "--allow-empty", "-m", "Third"]);
+
+    let prompt = render_with_outgoing(&clone_path, 2, false, false);
+    let commits = prompt
+        .lines()
+        .skip_while(|l| !l.contains("Git Outgoing"))
+        .skip(1)
+        .take_while(|l| !l.contains("more"))
+        .count();
+    assert_eq!(commits, 2, "{}", prompt);
+    assert_contains(&prompt, "Third");
+    assert_contains(&prompt, "… and 1 more");
+}
+
+#[test]
+fn outgoing_author_and_date() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+
+    let (_dir, clone_path) = clone_of(&origin);
+    git(&clone_path, &["commit", "--allow-empty", "-m", "Not pushed yet"]);
+
+    let prompt = render_with_outgoing(&clone_path, 10, true, true);
+    assert_contains(&prompt, " just now Test Not pushed yet");
+}
+
+#[test]
+fn outgoing_graph() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+
+    let (_dir, clone_path) = clone_of(&origin);
+    git(&clone_path, &["checkout", "-q", "-b", "feature"]);
+    git(&clone_path, &["commit", "--allow-empty", "-m", "Side commit"]);
+    git(&clone_path, &["checkout", "-q", "master"]);
+    git(&clone_path, &["commit", "--allow-empty", "-m", "Main commit"]);
+    git(&clone_path, &["merge", "-q", "--no-ff", "-m", "Merge feature", "feature"]);
+
+    let prompt = render(&clone_path);
+    let has_line = |glyph: &str, summary: &str| {
+        prompt
+            .lines()
+            .any(|l| l.contains(glyph) && l.contains(summary))
+    };
+    assert!(has_line("●╮ ", "Merge feature"), "{}", prompt);
+    assert!(has_line("│● ", "Side commit"), "{}", prompt);
+    assert!(has_line("●  ", "Main commit"), "{}", prompt);
+}
+
+#[test]
+fn bare_repository() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+
+    let bare_dir = TempDir::new("megaprompt-git").expect("Unable to create temp dir");
+    let bare_path = bare_dir.path().join("bare.git");
+    git2::build::RepoBuilder::new()
+        .bare(true)
+        .clone(&format!("{}", origin.path().display()), &bare_path)
+        .expect("Unable to clone");
+
+    let prompt = render(&bare_path);
+    assert_contains(&prompt, "┤bare├─┤master├─┤1 branch, 0 tags├");
+    assert!(!prompt.contains("Git Status"), "{}", prompt);
+    assert!(!prompt.contains("Git Outgoing"), "{}", prompt);
+}
+
+#[test]
+fn empty_bare_repository() {
+    let dir = TempDir::new("megaprompt-git").expect("Unable to create temp dir");
+    Repository::init_bare(dir.path()).expect("Unable to init repo");
+
+    let prompt = render(dir.path());
+    assert_contains(&prompt, "┤bare├─┤New Repository├─┤0 branches, 0 tags├");
+}
+
+#[test]
+fn inside_git_dir() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+    repo.write("a.txt", "changed\n");
+
+    for path in &[repo.path().join(".git"), repo.path().join(".git").join("refs")] {
+        let prompt = render(path);
+        assert_contains(&prompt, "┤GIT_DIR├─┤master├");
+        assert!(!prompt.contains("Git Status"), "{}", prompt);
+    }
+}
+
+#[test]
+fn linked_worktree() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+
+    let wt_dir = TempDir::new("megaprompt-git").expect("Unable to create temp dir");
+    let wt_path = wt_dir.path().join("wt");
+    repo.git(&[
+        "worktree",
+        "add",
+        "-b",
+        "feature",
+        &format!("{}", wt_path.display()),
+    ]);
+    File::create(wt_path.join("b.txt")).expect("Unable to create file");
+
+    let prompt = render(&wt_path);
+    assert_contains(&prompt, "┤worktree wt├─┤feature├");
+    assert_contains(&prompt, "?? b.txt");
+    assert_contains(&prompt, "Git Worktrees");
+    assert_contains(&prompt, "main: master (");
+    assert!(!prompt.contains("wt: feature"), "{}", prompt);
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤master├");
+    assert!(!prompt.contains("worktree"), "{}", prompt);
+    assert_contains(&prompt, "wt: feature (");
+    assert!(!prompt.contains("main: master"), "{}", prompt);
+}
+
+#[test]
+fn no_worktree_section_without_linked_worktrees() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains("Git Worktrees"), "{}", prompt);
+}
+
+#[test]
+fn submodules() {
+    let sub = TestRepo::new();
+    sub.write("lib.txt", "lib\n");
+    sub.commit_all("Library");
+
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+    repo.git(&[
+        "submodule",
+        "add",
+        &format!("{}", sub.path().display()),
+        "sub",
+    ]);
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "A  .gitmodules");
+    assert_contains(&prompt, "A  sub");
+
+    let prompt = render(&repo.path().join("sub"));
+    assert_contains(&prompt, "┤master -> origin/master├");
+}
+
+fn repo_with_submodule() -> (TestRepo, TestRepo) {
+    let sub = TestRepo::new();
+    sub.write("lib.txt", "lib\n");
+    sub.commit_all("Library");
+
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+    repo.git(&[
+        "submodule",
+        "add",
+        &format!("{}", sub.path().display()),
+        "sub",
+    ]);
+    repo.git(&["commit", "-m", "Add submodule"]);
+
+    (repo, sub)
+}
+
+#[test]
+fn clean_submodule_is_not_listed() {
+    let (repo, _sub) = repo_with_submodule();
+
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains("Git Submodules"), "{}", prompt);
+}
+
+#[test]
+fn dirty_submodule() {
+    let (repo, _sub) = repo_with_submodule();
+    repo.write("sub/lib.txt", "changed\n");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "Git Submodules");
+    assert_contains(&prompt, "sub: dirty");
+}
+
+#[test]
+fn submodule_at_different_commit() {
+    let (repo, _sub) = repo_with_submodule();
+    git(&repo.path().join("sub"), &["config", "user.name", "Test"]);
+    git(&repo.path().join("sub"), &["config", "user.email", "test@example.com"]);
+    git(&repo.path().join("sub"), &["commit", "--allow-empty", "-m", "Moved"]);
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "sub: checked out ");
+    assert_contains(&prompt, ", recorded ");
+}
+
+#[test]
+fn uninitialized_submodule() {
+    let (repo, _sub) = repo_with_submodule();
+
+    let clone_dir = TempDir::new("megaprompt-git").expect("Unable to create temp dir");
+    let clone_path = clone_dir.path().join("clone");
+    Repository::clone(&format!("{}", repo.path().display()), &clone_path)
+        .expect("Unable to clone");
+
+    let prompt = render(&clone_path);
+    assert_contains(&prompt, "sub: uninitialized");
+}
+
+#[test]
+fn last_fetch_age() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+    let (_dir, clone) = clone_of(&origin);
+
+    git(&clone, &["fetch"]);
+    let prompt = render(&clone);
+    assert_contains(&prompt, "┤master -> origin/master├─┤fetched just now├");
+
+    let output = Command::new("touch")
+        .args(&["-d", "5 days ago", ".git/FETCH_HEAD"])
+        .current_dir(&clone)
+        .output()
+        .expect("Unable to run touch");
+    assert!(output.status.success());
+    let prompt = render(&clone);
+    assert_contains(&prompt, "┤fetched 5 days ago├");
+}
+
+#[test]
+fn diff_stat() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "one\ntwo\nthree\n");
+    repo.commit_all("Initial commit");
+    repo.write("a.txt", "one\n2\nthree\nfour\n");
+    repo.write("b.txt", "b\nb\n");
+    repo.stage_all();
+
+    let prompt = render_with_diff_stat(&repo.path(), Duration::from_secs(10));
+    assert_contains(&prompt, "┤Git Status├─┤+4/-1├");
+    assert_contains(&prompt, "M  a.txt +2/-1");
+    assert_contains(&prompt, "A  b.txt +2/-0");
+
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains("+2/-1"), "{}", prompt);
+}
+
+#[test]
+fn diff_stat_over_budget() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+    repo.write("a.txt", "b\n");
+
+    let prompt = render_with_diff_stat(&repo.path(), Duration::from_secs(0));
+    assert_contains(&prompt, "┤+0/-0 (partial)├");
+    assert!(!prompt.contains("a.txt +"), "{}", prompt);
+}
+
+#[test]
+fn merge_conflicts() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "base\n");
+    repo.write("b.txt", "b\n");
+    repo.write("c.txt", "c\n");
+    repo.commit_all("Initial commit");
+    repo.git(&["checkout", "-q", "-b", "other"]);
+    repo.write("a.txt", "theirs\n");
+    repo.git(&["rm", "-q", "b.txt"]);
+    repo.commit_all("Their change");
+    repo.git(&["checkout", "-q", "master"]);
+    repo.write("a.txt", "ours\n");
+    repo.write("b.txt", "ours\n");
+    repo.commit_all("Our change");
+
+    let output = Command::new("git")
+        .args(&["merge", "-q", "other"])
+        .current_dir(repo.path())
+        .output()
+        .expect("Unable to run git");
+    assert!(!output.status.success(), "merge should conflict");
+    repo.write("c.txt", "changed\n");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤Git Status├");
+    assert_contains(&prompt, " M c.txt");
+    assert_contains(&prompt, "┤Git Conflicts├");
+    assert_contains(&prompt, "UU a.txt both modified 1 marker");
+    assert_contains(&prompt, "UD b.txt deleted by them");
+    assert!(!prompt.contains("UU a.txt\n"), "{}", prompt);
+
+    repo.write("a.txt", "resolved\n");
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "UU a.txt both modified no markers");
+}
+
+#[test]
+fn untracked_modes() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+    repo.write("build/out/one.o", "1\n");
+    repo.write("build/two.o", "2\n");
+
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "?? build");
+    assert!(!prompt.contains("two.o"), "{}", prompt);
+
+    repo.git(&["config", "megaprompt.untracked", "full"]);
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "?? build/out/one.o");
+    assert_contains(&prompt, "?? build/two.o");
+
+    // The .megaprompt file wins over git config
+    repo.write(".megaprompt", "git.untracked = off\n");
+    repo.git(&["config", "megaprompt.untracked", "collapsed"]);
+    repo.git(&["add", ".megaprompt"]);
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains("??"), "{}", prompt);
+    assert_contains(&prompt, "A  .megaprompt");
+}
+
+#[test]
+fn large_ignored_files() {
+    let repo = TestRepo::new();
+    repo.write(".gitignore", "target/\nsmall.log\n");
+    repo.commit_all("Initial commit");
+    repo.write("small.log", "log\n");
+    repo.write("target/debug/big", &"x".repeat(2 * 1024 * 1024));
+
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains("Git Status"), "{}", prompt);
+
+    repo.git(&["config", "megaprompt.ignoredWarning", "1"]);
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤Git Status├");
+    assert_contains(&prompt, "!! target/ over 1 MB");
+    assert!(!prompt.contains("small.log"), "{}", prompt);
+}
+
+#[test]
+fn large_ignored_directory_remeasured() {
+    let repo = TestRepo::new();
+    repo.write(".gitignore", "target/\n");
+    repo.commit_all("Initial commit");
+    repo.git(&["config", "megaprompt.ignoredWarning", "1"]);
+    repo.write("target/debug/big", &"x".repeat(2 * 1024 * 1024));
+
+    let mut buffer = PromptBuffer::new(ShellType::Bash);
+    buffer.add_plugin(plugin());
+    buffer.set_path(repo.path());
+    let prompt = plain(&buffer.convert_to_string_ext(PluginSpeed::Slow));
+    assert_contains(&prompt, "!! target/ over 1 MB");
+
+    // Removing a direct entry changes the directory's mtime
+    fs::remove_dir_all(repo.path().join("target/debug")).expect("Unable to remove dir");
+    repo.write("target/small", "x\n");
+    let prompt = plain(&buffer.convert_to_string_ext(PluginSpeed::Slow));
+    assert!(!prompt.contains("target/"), "{}", prompt);
+}
+
+#[test]
+fn divergence_from_default_branch() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+    origin.git(&["branch", "develop"]);
+
+    let (_dir, clone) = clone_of(&origin);
+    git(&clone, &["remote", "set-head", "origin", "master"]);
+    git(&clone, &["checkout", "-q", "-b", "feature"]);
+    git(&clone, &["commit", "--allow-empty", "-m", "One"]);
+    git(&clone, &["commit", "--allow-empty", "-m", "Two"]);
+
+    origin.write("a.txt", "b\n");
+    origin.commit_all("Upstream change");
+    git(&clone, &["fetch", "-q"]);
+
+    let prompt = render(&clone);
+    assert_contains(&prompt, "┤master +2/-1├");
+
+    let prompt = render_at(&clone, PluginSpeed::Fast);
+    assert!(!prompt.contains("master +"), "{}", prompt);
+
+    // No divergence shown on the base branch itself
+    git(&clone, &["checkout", "-q", "master"]);
+    let prompt = render(&clone);
+    assert!(!prompt.contains("master +"), "{}", prompt);
+
+    git(&clone, &["checkout", "-q", "feature"]);
+    git(&clone, &["config", "megaprompt.base", "origin/develop"]);
+    let prompt = render(&clone);
+    assert_contains(&prompt, "┤develop +2/-0├");
+}
+
+#[test]
+fn email_pattern() {
+    let repo = TestRepo::new();
+    repo.write("a.txt", "a\n");
+    repo.commit_all("Initial commit");
+
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains(" is not "), "{}", prompt);
+
+    repo.git(&["config", "megaprompt.emailPattern", "*@work.example"]);
+    let prompt = render(&repo.path());
+    assert_contains(&prompt, "┤test@example.com is not *@work.example├");
+
+    repo.git(&["config", "user.email", "me@work.example"]);
+    let prompt = render(&repo.path());
+    assert!(!prompt.contains(" is not "), "{}", prompt);
+}
+
+#[test]
+fn unsigned_outgoing_commits() {
+    let origin = TestRepo::new();
+    origin.write("a.txt", "a\n");
+    origin.commit_all("Initial commit");
+
+    let (_dir, clone) = clone_of(&origin);
+    git(&clone, &["commit", "--allow-empty", "-m", "Not signed"]);
+
+    // Rewrite a commit with a signature header, which is all the check reads
+    let repo = Repository::open(&clone).expect("Unable to open clone");
+    let head = repo.head()
+        .and_then(|h| h.peel_to_commit())
+        .expect("Unable to find HEAD");
+    let signed = format!(
+        "tree {}\nparent {}\nauthor Test <test@example.com> 0 +0000\n\
+         committer Test <test@example.com> 0 +0000\n\
+         gpgsig -----BEGIN PGP SIGNATURE-----\n \n -----END PGP SIGNATURE-----\n\nSigned\n",
+        head.tree_id(),
+        head.id()
+    );
+    let id = repo.odb()
+        .and_then(|odb| odb.write(git2::ObjectType::Commit, signed.as_bytes()))
+        .expect("Unable to write commit");
+    git(&clone, &["reset", "-q", "--hard", &format!("{}", id)]);
+
+    let prompt = render(&clone);
+    assert!(!prompt.contains("unsigned"), "{}", prompt);
+
+    git(&clone, &["config", "megaprompt.requireSigned", "true"]);
+    let prompt = render(&clone);
+    assert_contains(&prompt, "Not signed unsigned");
+    assert!(!prompt.contains("Signed unsigned"), "{}", prompt);
+}
@@ -0,0 +1,121 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Runs `HgPlugin` against a fake `.hg` directory, with a shell script
+//! standing in for `hg` so the tests don't need Mercurial installed.
+
+extern crate megaprompt;
+extern crate prompt_buffer;
+extern crate tempdir;
+
+mod common;
+
+use megaprompt::hg::HgPlugin;
+use prompt_buffer::{PluginSpeed, PromptBuffer, ShellType};
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tempdir::TempDir;
+
+use common::{isolate_settings, plain};
+
+const FAKE_HG: &'static str = r#"#!/bin/sh
+case "$1" in
+    status) printf 'M src/a.rs\nA b.rs\n? notes.txt\n' ;;
+    log) printf '1111111111aa First draft\n2222222222bb Second draft\n' ;;
+    *) exit 255 ;;
+esac
+"#;
+
+fn write(path: &Path, contents: &str) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("Unable to create dir");
+    }
+    let mut f = File::create(path).expect("Unable to create file");
+    f.write_all(contents.as_bytes())
+        .expect("Unable to write file");
+}
+
+fn script(dir: &Path, contents: &str) -> PathBuf {
+    let path = dir.join("hg");
+    write(&path, contents);
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("Unable to chmod");
+    path
+}
+
+fn render(plugin: HgPlugin, path: &Path, speed: PluginSpeed) -> String {
+    let mut buffer = PromptBuffer::new(ShellType::Bash);
+    buffer.add_plugin(plugin);
+    buffer.set_path(path.to_path_buf());
+    plain(&buffer.convert_to_string_ext(speed))
+}
+
+/// A plugin that only sees the defaults, not the settings of whoever runs the tests
+fn plugin() -> HgPlugin {
+    isolate_settings();
+    HgPlugin::new()
+}
+
+fn repo() -> (TempDir, HgPlugin) {
+    let dir = TempDir::new("megaprompt-hg").expect("Unable to create temp dir");
+    write(&dir.path().join("repo/.hg/branch"), "stable\n");
+    write(&dir.path().join("repo/.hg/bookmarks.current"), "feature");
+    fs::create_dir_all(dir.path().join("repo/src")).expect("Unable to create dir");
+
+    let mut plugin = plugin();
+    plugin.set_command(format!("{}", script(dir.path(), FAKE_HG).display()));
+    (dir, plugin)
+}
+
+#[test]
+fn not_a_repository() {
+    let dir = TempDir::new("megaprompt-hg").expect("Unable to create temp dir");
+    let prompt = render(plugin(), dir.path(), PluginSpeed::Slow);
+    assert!(!prompt.contains("hg"), "{}", prompt);
+}
+
+#[test]
+fn status_outgoing_and_branch() {
+    let (dir, plugin) = repo();
+    let prompt = render(plugin, &dir.path().join("repo/src"), PluginSpeed::Slow);
+
+    assert!(prompt.contains("┤Hg Status├"), "{}", prompt);
+    assert!(prompt.contains("M src/a.rs"), "{}", prompt);
+    assert!(prompt.contains("? notes.txt"), "{}", prompt);
+    assert!(prompt.contains("┤Hg Outgoing├"), "{}", prompt);
+    let second = prompt.find("Second draft").expect("Missing changeset");
+    let first = prompt.find("First draft").expect("Missing changeset");
+    assert!(second < first, "{}", prompt);
+    assert!(prompt.contains("┤hg├─┤stable├─┤@feature├"), "{}", prompt);
+}
+
+#[test]
+fn fast_prompt_skips_hg() {
+    let (dir, mut plugin) = repo();
+    plugin.set_command("/nonexistent/hg");
+    let prompt = render(plugin, &dir.path().join("repo"), PluginSpeed::Fast);
+
+    assert!(!prompt.contains("Hg Status"), "{}", prompt);
+    assert!(prompt.contains("┤hg├─┤stable├─┤@feature├"), "{}", prompt);
+}
+
+#[test]
+fn slow_hg_is_killed() {
+    let (dir, mut plugin) = repo();
+    let slow = script(dir.path(), "#!/bin/sh\nsleep 10\n");
+    plugin.set_command(format!("{}", slow.display()));
+    plugin.set_timeout(Duration::from_millis(100));
+
+    let start = Instant::now();
+    let prompt = render(plugin, &dir.path().join("repo"), PluginSpeed::Slow);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(!prompt.contains("Hg Status"), "{}", prompt);
+    assert!(prompt.contains("┤hg├─┤stable├"), "{}", prompt);
+}